@@ -1,30 +1,68 @@
-use anyhow::{anyhow, Result};
-use clap::Parser;
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, Context, Result};
+use clap::{Parser, Subcommand};
+use data_mingler_rust::{
+    dvmql::dvm::{load_dvm_xml, Dvm, DvmEdge},
+    neo4j::Neo4jArgs,
+    store::SqliteStore,
+};
 use neo4rs::{query, Graph};
-use quick_xml::events::Event;
-use tokio::fs::File;
-use tracing::{debug, info, trace, Level};
+use tracing::{debug, info, Level};
 use tracing_subscriber::FmtSubscriber;
 
+/// Loads a DVM file into Neo4j, or into a SQLite file
 #[derive(Parser, Debug)]
-#[command(author, version, about)]
+#[command(
+    author,
+    version,
+    about,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Args {
-    dvm_file_path: String,
-    #[arg(long, default_value_t = String::from("bolt://localhost:7687"))]
-    bolt_uri: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[arg(required = true)]
+    dvm_file_path: Option<String>,
     #[arg(long)]
     use_existing_graph: bool,
+    /// Number of edges loaded per transaction
+    #[arg(long, default_value_t = 1000)]
+    batch_size: usize,
+    /// Load the DVM into this SQLite file instead of Neo4j
+    #[arg(long)]
+    sqlite_path: Option<String>,
+    #[command(flatten)]
+    common: CommonArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct CommonArgs {
+    #[command(flatten)]
+    neo4j: Neo4jArgs,
     #[arg(short, long, action = clap::ArgAction::Count)]
     debug: u8,
 }
 
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Recomputes the primary labels of an existing graph, e.g. after editing it by hand
+    RecomputePrimary(CommonArgs),
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // Parse CLI arguments
     let args = Args::parse();
 
+    let common = match &args.command {
+        Some(Command::RecomputePrimary(common)) => common,
+        None => &args.common,
+    };
+
     // Initialize logger
-    let log_level = match args.debug {
+    let log_level = match common.debug {
         1 => Level::INFO,
         2 => Level::DEBUG,
         3 => Level::TRACE,
@@ -32,14 +70,40 @@ async fn main() -> Result<()> {
     };
     let subscriber = FmtSubscriber::builder().with_max_level(log_level).finish();
     tracing::subscriber::set_global_default(subscriber).expect("Setting default subscriber failed");
+
+    if let Some(Command::RecomputePrimary(_)) = args.command {
+        let neo4j = common.neo4j.resolve()?.connect().await?;
+        debug!("Connected to Neo4j");
+        recompute_primary(&neo4j).await?;
+        info!("Finished recomputing primary nodes");
+        return Ok(());
+    }
+    let dvm_file_path = args
+        .dvm_file_path
+        .as_deref()
+        .context("A DVM file path is required")?;
     info!("Starting DVM to Neo4j loader...");
 
+    // Parse the whole DVM before touching the graph, so that an invalid file leaves it intact
+    let dvm = load_dvm_xml(dvm_file_path)?;
+    let batch_size = args.batch_size.max(1);
+
+    if let Some(sqlite_path) = &args.sqlite_path {
+        let store = SqliteStore::open(sqlite_path)?;
+        if !args.use_existing_graph {
+            store.clear()?;
+            debug!("Cleared the SQLite DVM");
+        }
+        for batch in dvm.edges.chunks(batch_size) {
+            debug!("Storing batch of {} edges", batch.len());
+            store.store_edges(batch)?;
+        }
+        info!("Finished loading DVM to SQLite file \"{}\"", sqlite_path);
+        return Ok(());
+    }
+
     // Initialize Neo4j graph
-    let neo4j = Graph::new(args.bolt_uri, "neo4j", "12345678").await?;
-    assert!(
-        neo4j.run(query("RETURN 1")).await.is_ok(),
-        "Failed to connect to Neo4j"
-    );
+    let neo4j = common.neo4j.resolve()?.connect().await?;
     debug!("Connected to Neo4j");
 
     if !args.use_existing_graph {
@@ -48,165 +112,109 @@ async fn main() -> Result<()> {
         debug!("Cleared the graph");
     }
 
-    load_dvm_to_neo4j(&neo4j, &args.dvm_file_path).await?;
+    // Attribute names identify nodes, so that loading merges into the existing ones
+    neo4j
+        .run(query(
+            "CREATE CONSTRAINT attribute_name IF NOT EXISTS \
+            FOR (n:attribute) REQUIRE n.name IS UNIQUE",
+        ))
+        .await?;
+    debug!("Ensured uniqueness constraint on attribute names");
+
+    load_dvm_to_neo4j(&neo4j, &dvm, batch_size).await?;
+    recompute_primary(&neo4j).await?;
 
     info!("Finished loading DVM to Neo4j");
     Ok(())
 }
 
-async fn load_dvm_to_neo4j(graph: &Graph, dvm_file_path: &str) -> Result<()> {
-    info!("Reading from DVM file \"{}\"", dvm_file_path);
-    let file = File::open(dvm_file_path).await?;
-    let reader = tokio::io::BufReader::new(file);
-    let mut reader = quick_xml::Reader::from_reader(reader);
-
-    let mut buf = Vec::new();
-    loop {
-        let mut node_a_name = String::new();
-        let mut node_a_description = String::new();
-        let mut node_b_name = String::new();
-        let mut node_b_description = String::new();
-        let mut datasource_name = String::new();
-        let mut query_string = String::new();
-        let mut pos1 = String::new();
-        let mut pos2 = String::new();
-
-        let mut in_headnode = false;
-        let mut in_tailnode = false;
-        let mut in_datasource = false;
-        let mut in_query = false;
-        let mut in_key = false;
-        let mut in_value = false;
-
-        loop {
-            match reader.read_event_into_async(&mut buf).await {
-                Ok(Event::Start(ref e)) => match e.name().local_name().as_ref() {
-                    b"headnode" => in_headnode = true,
-                    b"tailnode" => in_tailnode = true,
-                    b"datasource" => in_datasource = true,
-                    b"query" => in_query = true,
-                    b"key" => in_key = true,
-                    b"value" => in_value = true,
-                    b"name" | b"description" | b"edge" | b"edges" => (),
-                    _ => return Err(anyhow!("Unexpected start tag: {:?}", e))?,
-                },
-                Ok(Event::End(ref e)) => match e.name().local_name().as_ref() {
-                    b"headnode" => in_headnode = false,
-                    b"tailnode" => in_tailnode = false,
-                    b"datasource" => in_datasource = false,
-                    b"query" => in_query = false,
-                    b"key" => in_key = false,
-                    b"value" => in_value = false,
-                    b"name" | b"description" | b"edges" => (),
-                    b"edge" => break,
-                    _ => return Err(anyhow!("Unexpected end tag: {:?}", e))?,
-                },
-                Ok(Event::Text(e)) => {
-                    let text = e.unescape()?.trim().to_owned();
-
-                    if text.is_empty() {
-                        continue;
-                    }
-
-                    if in_headnode {
-                        if node_a_name.is_empty() {
-                            node_a_name.clone_from(&text);
-                        } else {
-                            node_a_description.clone_from(&text);
-                        }
-                    } else if in_tailnode {
-                        if node_b_name.is_empty() {
-                            node_b_name.clone_from(&text);
-                        } else {
-                            node_b_description.clone_from(&text);
-                        }
-                    } else if in_datasource {
-                        datasource_name.clone_from(&text);
-                    } else if in_query {
-                        query_string.clone_from(&text);
-                    } else if in_key {
-                        pos1.clone_from(&text);
-                    } else if in_value {
-                        pos2.clone_from(&text);
-                    } else {
-                        return Err(anyhow!("Unexpected text: {:?}", text))?;
-                    }
-                }
-                Ok(Event::Eof) => return Ok(()),
-                Err(e) => return Err(anyhow!("Error reading event: {:?}", e)),
-                _ => (),
-            }
-
-            buf.clear();
-        }
-
-        debug!("Storing edge: \"{}\" -> \"{}\"", node_a_name, node_b_name);
-        let store_node_a = store_node_in_neo4j(graph, &node_a_name, &node_a_description);
-        let store_node_b = store_node_in_neo4j(graph, &node_b_name, &node_b_description);
-        tokio::try_join!(store_node_a, store_node_b).map_err(|e| {
-            anyhow!(format!(
-                "Error storing while edge \"{}\" -> \"{}\": {}",
-                node_a_name, node_b_name, e
-            ))
-        })?;
-
-        graph.run(query(
-            "MATCH (a:attribute), (b:attribute) \
-            WHERE a.name = $nodeA_name AND b.name = $nodeB_name \
-            CREATE (a)-[:has {datasource: $datasource_name, query: $query_string, key: $pos1, value: $pos2, selected: 'false'}]->(b)")
-            .param("nodeA_name", node_a_name.clone())
-            .param("nodeB_name", node_b_name.clone())
-            .param("datasource_name", datasource_name)
-            .param("query_string", query_string)
-            .param("pos1", pos1)
-            .param("pos2", pos2)
-        ).await?;
-
-        let node_a_query = graph.run(
-            query(
-                "MATCH (n:attribute {name: $nodeA_name})-[:has]->(b) \
-            WITH n, count(b) AS cnt \
-            WHERE cnt > 1 \
-            SET n:primary",
-            )
-            .param("nodeA_name", node_a_name),
-        );
-
-        let node_b_query = graph.run(
-            query(
-                "MATCH (n:attribute {name: $nodeB_name})-[:has]->(b) \
-            WITH n, count(b) AS cnt \
-            WHERE cnt > 1 \
-            SET n:primary",
-            )
-            .param("nodeB_name", node_b_name),
-        );
-
-        tokio::try_join!(node_a_query, node_b_query).map_err(|e| anyhow!(e))?;
+/// Stores the edges of the DVM in batches of `batch_size`.
+async fn load_dvm_to_neo4j(graph: &Graph, dvm: &Dvm, batch_size: usize) -> Result<()> {
+    for batch in dvm.edges.chunks(batch_size) {
+        store_edges_in_neo4j(graph, batch).await?;
     }
+    Ok(())
 }
 
-async fn store_node_in_neo4j(neo4j: &Graph, node_name: &str, node_description: &str) -> Result<()> {
-    let mut exists_query_result = neo4j
-        .execute(
-            query("MATCH (n:attribute {name: $nodeName}) RETURN n.name AS name")
-                .param("nodeName", node_name),
-        )
-        .await?;
-    if (exists_query_result.next().await?).is_none() {
-        trace!("Node does not exist, creating node {}", node_name);
-        neo4j
-            .run(
-                query("CREATE (n:attribute {name: $nodeName, description: $nodeDescription})")
-                    .param("nodeName", node_name)
-                    .param("nodeDescription", node_description),
-            )
-            .await?;
-    } else {
-        trace!(
-            "Node already exists, skipping creation of \"{}\"",
-            node_name
-        );
+/// Stores a batch of edges and their nodes in a single transaction.
+async fn store_edges_in_neo4j(graph: &Graph, edges: &[DvmEdge]) -> Result<()> {
+    if edges.is_empty() {
+        return Ok(());
     }
+    debug!("Storing batch of {} edges", edges.len());
+
+    // Keep the first description of each node, like the nodes already in the graph
+    let mut nodes: Vec<HashMap<String, String>> = vec![];
+    let mut names: HashSet<&str> = HashSet::new();
+    for (name, description) in edges.iter().flat_map(|edge| {
+        [
+            (&edge.head.name, &edge.head.description),
+            (&edge.tail.name, &edge.tail.description),
+        ]
+    }) {
+        if !names.insert(name) {
+            continue;
+        }
+        nodes.push(HashMap::from([
+            ("name".to_string(), name.clone()),
+            ("description".to_string(), description.clone()),
+        ]));
+    }
+    let edge_count = edges.len();
+    let edges: Vec<HashMap<String, String>> = edges
+        .iter()
+        .map(|edge| {
+            HashMap::from([
+                ("head".to_string(), edge.head.name.clone()),
+                ("tail".to_string(), edge.tail.name.clone()),
+                ("datasource".to_string(), edge.datasource.clone()),
+                ("query".to_string(), edge.query.clone()),
+                ("key".to_string(), edge.key.to_string()),
+                ("value".to_string(), edge.value.to_string()),
+            ])
+        })
+        .collect();
+
+    let mut txn = graph.start_txn().await?;
+    txn.run_queries([
+        query(
+            "UNWIND $nodes AS node \
+            MERGE (n:attribute {name: node.name}) \
+            ON CREATE SET n.description = node.description",
+        )
+        .param("nodes", nodes),
+        query(
+            "UNWIND $edges AS edge \
+            MATCH (a:attribute {name: edge.head}), (b:attribute {name: edge.tail}) \
+            MERGE (a)-[r:has {datasource: edge.datasource}]->(b) \
+            ON CREATE SET r.selected = 'false' \
+            SET r.query = edge.query, r.key = edge.key, r.value = edge.value",
+        )
+        .param("edges", edges),
+    ])
+    .await
+    .map_err(|e| anyhow!("Error storing batch of {} edges: {}", edge_count, e))?;
+    txn.commit().await?;
+    Ok(())
+}
+
+/// Labels as primary the nodes with more than one outgoing edge, and unlabels the rest.
+async fn recompute_primary(graph: &Graph) -> Result<()> {
+    let mut txn = graph.start_txn().await?;
+    txn.run_queries([
+        "MATCH (n:attribute) \
+        OPTIONAL MATCH (n)-[:has]->(b) \
+        WITH n, count(b) AS cnt \
+        WHERE cnt <= 1 \
+        REMOVE n:primary",
+        "MATCH (n:attribute)-[:has]->(b) \
+        WITH n, count(b) AS cnt \
+        WHERE cnt > 1 \
+        SET n:primary",
+    ])
+    .await
+    .map_err(|e| anyhow!("Error recomputing primary nodes: {}", e))?;
+    txn.commit().await?;
+    debug!("Recomputed primary nodes");
     Ok(())
 }
@@ -0,0 +1,121 @@
+use anyhow::{Context, Result};
+use clap::Parser;
+use data_mingler_rust::{
+    dvmql::dvm::{Dvm, DvmEdge, DvmNode},
+    load::edges::deserialize_number_from_string,
+    neo4j::Neo4jArgs,
+};
+use neo4rs::{query, Graph};
+use serde::Deserialize;
+use tracing::{debug, info, trace, warn, Level};
+use tracing_subscriber::FmtSubscriber;
+
+const EDGES_QUERY: &str = "MATCH (a:attribute)-[r:has]->(b:attribute) \
+    RETURN a.name AS head_name, a.description AS head_description, \
+    b.name AS tail_name, b.description AS tail_description, \
+    r.datasource AS datasource, r.query AS query, r.key AS key, r.value AS value \
+    ORDER BY head_name, tail_name, datasource";
+
+const ISOLATED_QUERY: &str =
+    "MATCH (n:attribute) WHERE NOT (n)-[:has]-() RETURN n.name AS name ORDER BY name";
+
+/// Exports the DVM stored in Neo4j into a DVM file
+#[derive(Parser, Debug)]
+#[command(author, version, about)]
+struct Args {
+    dvm_file_path: String,
+    #[command(flatten)]
+    neo4j: Neo4jArgs,
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    debug: u8,
+}
+
+/// Edge returned by `EDGES_QUERY`, along with its head and tail nodes.
+#[derive(Deserialize, Debug)]
+struct StoredEdge {
+    head_name: String,
+    head_description: Option<String>,
+    tail_name: String,
+    tail_description: Option<String>,
+    datasource: String,
+    query: Option<String>,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    key: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    value: u32,
+}
+
+impl From<StoredEdge> for DvmEdge {
+    fn from(edge: StoredEdge) -> DvmEdge {
+        DvmEdge {
+            head: DvmNode {
+                name: edge.head_name,
+                description: edge.head_description.unwrap_or_default(),
+            },
+            tail: DvmNode {
+                name: edge.tail_name,
+                description: edge.tail_description.unwrap_or_default(),
+            },
+            datasource: edge.datasource,
+            query: edge.query.unwrap_or_default(),
+            key: edge.key,
+            value: edge.value,
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    // Parse CLI arguments
+    let args = Args::parse();
+
+    // Initialize logger
+    let log_level = match args.debug {
+        1 => Level::INFO,
+        2 => Level::DEBUG,
+        3 => Level::TRACE,
+        _ => Level::ERROR,
+    };
+    let subscriber = FmtSubscriber::builder().with_max_level(log_level).finish();
+    tracing::subscriber::set_global_default(subscriber).expect("Setting default subscriber failed");
+    info!("Starting Neo4j to DVM exporter...");
+
+    // Initialize Neo4j graph
+    let neo4j = args.neo4j.resolve()?.connect().await?;
+    debug!("Connected to Neo4j");
+
+    let dvm = read_dvm_from_neo4j(&neo4j).await?;
+    std::fs::write(&args.dvm_file_path, dvm.to_xml())
+        .with_context(|| format!("Failed to write DVM file: {}", &args.dvm_file_path))?;
+
+    info!(
+        "Finished exporting {} edges to \"{}\"",
+        dvm.edges.len(),
+        &args.dvm_file_path
+    );
+    Ok(())
+}
+
+/// Reads every `has` edge of the graph, ordered so that exports of the same graph are identical.
+async fn read_dvm_from_neo4j(graph: &Graph) -> Result<Dvm> {
+    let mut edges = vec![];
+    let mut result = graph.execute(query(EDGES_QUERY)).await?;
+    while let Some(row) = result.next().await? {
+        let edge: StoredEdge = row.to().context("Failed to read edge from Neo4j")?;
+        trace!(
+            "Read edge: \"{}\" -> \"{}\"",
+            edge.head_name,
+            edge.tail_name
+        );
+        edges.push(DvmEdge::from(edge));
+    }
+    debug!("Read {} edges from Neo4j", edges.len());
+
+    // The DVM file only holds edges, so nodes without any are lost
+    let mut result = graph.execute(query(ISOLATED_QUERY)).await?;
+    while let Some(row) = result.next().await? {
+        let name: String = row.get("name").context("Failed to read node from Neo4j")?;
+        warn!("Node \"{}\" has no edges and is not exported", name);
+    }
+    Ok(Dvm { edges })
+}
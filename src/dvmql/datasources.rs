@@ -2,42 +2,88 @@
 //!
 //! This module containts deserialization logic for the datasources XML file.
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use serde::Deserialize;
-use std::collections::HashMap;
+use std::{collections::HashMap, str::FromStr};
+use thiserror::Error;
 use tracing::{debug, info, trace};
 
-use crate::load::{Csv, Database, Datasource, Excel, Xml};
+use crate::load::{Csv, Database, DatabaseSystem, Datasource, Excel, Xml};
 
 use super::helpers::read_xml_file;
 
 /// Helper function for loading and deserializing the datasources XML file
+///
+/// Every datasource is validated before failing, so that all invalid datasources are reported at once.
 pub fn load_datasources_xml(datasources_path: &str) -> Result<HashMap<String, Datasource>> {
+    let (datasources, errors) = load_valid_datasources_xml(datasources_path)?;
+    if !errors.is_empty() {
+        return Err(DatasourceErrors(errors))
+            .with_context(|| format!("Invalid datasources XML {}", datasources_path));
+    }
+    Ok(datasources)
+}
+
+/// Helper function for loading the valid datasources of the datasources XML file, along with
+/// the errors of the invalid ones.
+pub fn load_valid_datasources_xml(
+    datasources_path: &str,
+) -> Result<(HashMap<String, Datasource>, Vec<DatasourceError>)> {
     info!("Loading datasources from {}", datasources_path);
     let init_datasources: DeserializedDatasources = read_xml_file(datasources_path)?;
     debug!("Deserialized datasources from XML {}", datasources_path);
-    let res: HashMap<String, Datasource> = init_datasources
-        .datasource
-        .iter()
-        .map(|init_ds| {
-            let ds = match init_ds.ds_type.as_str() {
-                "csv" => Datasource::Csv(Csv::from(init_ds)),
-                "xml" => Datasource::Xml(Xml::from(init_ds)),
-                "db" => Datasource::Database(Database::from(init_ds)),
-                "excel" => Datasource::Excel(Excel::from(init_ds)),
-                // TODO: Handle error
-                &_ => panic!("Incorrect datasource type given: {}", init_ds.ds_type),
-            };
-            trace!(
-                "Collecting {} datasource: {}",
-                init_ds.ds_type.to_uppercase(),
-                init_ds.name
-            );
-            (init_ds.name.clone(), ds)
-        })
-        .collect();
-    debug!("Built collection of datasources from datasources XML");
-    Ok(res)
+    let mut res: HashMap<String, Datasource> = HashMap::new();
+    let mut errors = vec![];
+    for init_ds in &init_datasources.datasource {
+        match Datasource::try_from(init_ds) {
+            Ok(ds) => {
+                trace!(
+                    "Collecting {} datasource: {}",
+                    init_ds.ds_type.to_uppercase(),
+                    init_ds.name
+                );
+                res.insert(init_ds.name.clone(), ds);
+            }
+            Err(e) => errors.push(e),
+        }
+    }
+    debug!(
+        "Built collection of {} datasources from datasources XML, {} invalid",
+        res.len(),
+        errors.len()
+    );
+    Ok((res, errors))
+}
+
+/// Error of an invalid datasource in the datasources XML file.
+#[derive(Debug, Error, PartialEq)]
+pub enum DatasourceError {
+    #[error("Datasource {name} has unknown type \"{ds_type}\". Supported types are csv, xml, excel and db.")]
+    UnknownType { name: String, ds_type: String },
+    #[error("{} datasource {name} is missing the {field} field", .ds_type.to_uppercase())]
+    MissingField {
+        name: String,
+        ds_type: String,
+        field: &'static str,
+    },
+    #[error("Unsupported database system {system} for datasource {name}. Supported systems are postgresql, sqlite and mysql.")]
+    UnsupportedSystem { name: String, system: String },
+}
+
+/// Errors of all the invalid datasources in the datasources XML file.
+#[derive(Debug, Error, PartialEq)]
+#[error("{}", .0.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("\n"))]
+pub struct DatasourceErrors(pub Vec<DatasourceError>);
+
+impl DatasourceError {
+    /// Name of the invalid datasource.
+    pub fn name(&self) -> &str {
+        match self {
+            DatasourceError::UnknownType { name, .. }
+            | DatasourceError::MissingField { name, .. }
+            | DatasourceError::UnsupportedSystem { name, .. } => name,
+        }
+    }
 }
 
 /// Intermediate representation of the deserialized datasources XML file.
@@ -67,103 +113,105 @@ struct DeserializedDatasource {
 
 const HEADINGS_HAYSTACK: [&str; 4] = ["yes", "true", "y", "1"];
 
-impl From<&DeserializedDatasource> for Csv {
-    fn from(ds: &DeserializedDatasource) -> Csv {
-        Csv {
+impl DeserializedDatasource {
+    /// Helper function for getting a field required by the type of the datasource.
+    fn required<T: Clone>(
+        &self,
+        field: &'static str,
+        value: &Option<T>,
+    ) -> Result<T, DatasourceError> {
+        value.clone().ok_or_else(|| DatasourceError::MissingField {
+            name: self.name.clone(),
+            ds_type: self.ds_type.clone(),
+            field,
+        })
+    }
+}
+
+impl TryFrom<&DeserializedDatasource> for Datasource {
+    type Error = DatasourceError;
+
+    fn try_from(ds: &DeserializedDatasource) -> Result<Datasource, DatasourceError> {
+        Ok(match ds.ds_type.as_str() {
+            "csv" => Datasource::Csv(Csv::try_from(ds)?),
+            "xml" => Datasource::Xml(Xml::try_from(ds)?),
+            "db" => Datasource::Database(Database::try_from(ds)?),
+            "excel" => Datasource::Excel(Excel::try_from(ds)?),
+            _ => {
+                return Err(DatasourceError::UnknownType {
+                    name: ds.name.clone(),
+                    ds_type: ds.ds_type.clone(),
+                })
+            }
+        })
+    }
+}
+
+impl TryFrom<&DeserializedDatasource> for Csv {
+    type Error = DatasourceError;
+
+    fn try_from(ds: &DeserializedDatasource) -> Result<Csv, DatasourceError> {
+        Ok(Csv {
             id: ds.id,
             name: ds.name.to_owned(),
-            filename: ds
-                .filename
-                .to_owned()
-                .expect("CSV filename field should be defined"),
-            path: ds
-                .path
-                .to_owned()
-                .expect("CSV path field should be defined"),
-            delimiter: ds
-                .delimiter
-                .to_owned()
-                .expect("CSV delimiter field should be defined"),
-            has_headers: HEADINGS_HAYSTACK.contains(
-                &ds.headings
-                    .to_owned()
-                    .expect("CSV headings should be defined")
-                    .as_str(),
-            ),
-        }
+            filename: ds.required("filename", &ds.filename)?,
+            path: ds.required("path", &ds.path)?,
+            delimiter: ds.required("delimiter", &ds.delimiter)?,
+            has_headers: HEADINGS_HAYSTACK
+                .contains(&ds.required("headings", &ds.headings)?.as_str()),
+        })
     }
 }
 
-impl From<&DeserializedDatasource> for Xml {
-    fn from(ds: &DeserializedDatasource) -> Xml {
-        Xml {
+impl TryFrom<&DeserializedDatasource> for Xml {
+    type Error = DatasourceError;
+
+    fn try_from(ds: &DeserializedDatasource) -> Result<Xml, DatasourceError> {
+        Ok(Xml {
             id: ds.id,
             name: ds.name.to_owned(),
-            filename: ds
-                .filename
-                .to_owned()
-                .expect("CSV filename field should be defined"),
-            path: ds
-                .path
-                .to_owned()
-                .expect("CSV path field should be defined"),
-        }
+            filename: ds.required("filename", &ds.filename)?,
+            path: ds.required("path", &ds.path)?,
+        })
     }
 }
 
-impl From<&DeserializedDatasource> for Excel {
-    fn from(ds: &DeserializedDatasource) -> Excel {
-        Excel {
+impl TryFrom<&DeserializedDatasource> for Excel {
+    type Error = DatasourceError;
+
+    fn try_from(ds: &DeserializedDatasource) -> Result<Excel, DatasourceError> {
+        Ok(Excel {
             id: ds.id,
             name: ds.name.to_owned(),
-            filename: ds
-                .filename
-                .to_owned()
-                .expect("Excel filename field should be defined"),
-            path: ds
-                .path
-                .to_owned()
-                .expect("Excel path field should be defined"),
-            sheet: ds
-                .sheet
-                .to_owned()
-                .expect("Excel sheet field should be defined"),
-            has_headers: HEADINGS_HAYSTACK.contains(
-                &ds.headings
-                    .to_owned()
-                    .expect("Excel headings should be defined")
-                    .as_str(),
-            ),
-        }
+            filename: ds.required("filename", &ds.filename)?,
+            path: ds.required("path", &ds.path)?,
+            sheet: ds.required("sheet", &ds.sheet)?,
+            has_headers: HEADINGS_HAYSTACK
+                .contains(&ds.required("headings", &ds.headings)?.as_str()),
+        })
     }
 }
 
-impl From<&DeserializedDatasource> for Database {
-    fn from(ds: &DeserializedDatasource) -> Database {
-        Database {
+impl TryFrom<&DeserializedDatasource> for Database {
+    type Error = DatasourceError;
+
+    fn try_from(ds: &DeserializedDatasource) -> Result<Database, DatasourceError> {
+        let system = ds.required("system", &ds.system)?;
+        if DatabaseSystem::from_str(&system).is_err() {
+            return Err(DatasourceError::UnsupportedSystem {
+                name: ds.name.clone(),
+                system,
+            });
+        }
+        Ok(Database {
             id: ds.id,
             name: ds.name.to_owned(),
-            system: ds
-                .system
-                .to_owned()
-                .expect("Database system field should be defined"),
-            connection: ds
-                .connection
-                .to_owned()
-                .expect("Database connection field should be defined"),
-            username: ds
-                .username
-                .to_owned()
-                .expect("Database username field should be defined"),
-            password: ds
-                .password
-                .to_owned()
-                .expect("Database password field should be defined"),
-            database: ds
-                .database
-                .to_owned()
-                .expect("Database database field should be defined"),
-        }
+            system,
+            connection: ds.required("connection", &ds.connection)?,
+            username: ds.required("username", &ds.username)?,
+            password: ds.required("password", &ds.password)?,
+            database: ds.required("database", &ds.database)?,
+        })
     }
 }
 
@@ -306,4 +354,69 @@ mod tests {
         let datasources = load_datasources_xml(&path).unwrap();
         assert_eq!(datasources, get_datasources());
     }
+
+    #[test]
+    fn test_load_datasources_xml_rejects_unknown_database_system() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let path = dir.path().join("datasources.xml");
+        std::fs::write(
+            &path,
+            "<datasources><datasource type=\"db\"><id>1</id><name>myDb</name>\
+            <system>oracle</system><connection>localhost</connection><username>u</username>\
+            <password>p</password><database>d</database></datasource></datasources>",
+        )
+        .unwrap();
+        let err = load_datasources_xml(path.to_str().unwrap()).unwrap_err();
+        assert!(format!("{:#}", err).contains("Unsupported database system oracle"));
+    }
+
+    #[test]
+    fn test_load_datasources_xml_reports_every_invalid_datasource() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let path = dir.path().join("datasources.xml");
+        std::fs::write(
+            &path,
+            "<datasources>\
+            <datasource type=\"json\"><id>1</id><name>myJson</name></datasource>\
+            <datasource type=\"csv\"><id>2</id><name>myCSV</name><filename>f.csv</filename>\
+            <path>/</path><headings>yes</headings></datasource>\
+            <datasource type=\"xml\"><id>3</id><name>myXml</name><path>/</path></datasource>\
+            <datasource type=\"excel\"><id>4</id><name>myExcel</name><filename>f.xlsx</filename>\
+            <path>/</path><sheet>S</sheet><headings>no</headings></datasource>\
+            </datasources>",
+        )
+        .unwrap();
+        let err = load_datasources_xml(path.to_str().unwrap()).unwrap_err();
+        let errors = err.downcast_ref::<DatasourceErrors>().unwrap();
+        assert_eq!(
+            errors,
+            &DatasourceErrors(vec![
+                DatasourceError::UnknownType {
+                    name: String::from("myJson"),
+                    ds_type: String::from("json"),
+                },
+                DatasourceError::MissingField {
+                    name: String::from("myCSV"),
+                    ds_type: String::from("csv"),
+                    field: "delimiter",
+                },
+                DatasourceError::MissingField {
+                    name: String::from("myXml"),
+                    ds_type: String::from("xml"),
+                    field: "filename",
+                },
+            ])
+        );
+        assert_eq!(
+            errors.0[2].to_string(),
+            "XML datasource myXml is missing the filename field"
+        );
+
+        let (datasources, errors) = load_valid_datasources_xml(path.to_str().unwrap()).unwrap();
+        assert_eq!(datasources.keys().collect::<Vec<_>>(), vec!["myExcel"]);
+        assert_eq!(
+            errors.iter().map(|e| e.name()).collect::<Vec<_>>(),
+            vec!["myJson", "myCSV", "myXml"]
+        );
+    }
 }
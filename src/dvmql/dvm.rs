@@ -0,0 +1,292 @@
+//! # DVM
+//!
+//! This module contains deserialization logic for the DVM XML file, which lists
+//! the edges of the DVM along with their head and tail nodes.
+
+use anyhow::{bail, Context, Result};
+use quick_xml::{escape::partial_escape, events::Event};
+use serde::{de::Deserializer, Deserialize};
+use tracing::{debug, info, trace};
+
+/// DVM described by the DVM XML file.
+#[derive(Debug, Default, PartialEq)]
+pub struct Dvm {
+    pub edges: Vec<DvmEdge>,
+}
+
+/// Edge of the DVM XML file, along with its head and tail nodes.
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct DvmEdge {
+    #[serde(rename = "headnode")]
+    pub head: DvmNode,
+    #[serde(rename = "tailnode")]
+    pub tail: DvmNode,
+    pub datasource: String,
+    #[serde(default)]
+    pub query: String,
+    #[serde(deserialize_with = "deserialize_position")]
+    pub key: u32,
+    #[serde(deserialize_with = "deserialize_position")]
+    pub value: u32,
+}
+
+/// Head or tail node of an edge in the DVM XML file.
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct DvmNode {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+}
+
+impl Dvm {
+    /// Serializes the DVM into the layout of the DVM XML file.
+    pub fn to_xml(&self) -> String {
+        let mut xml = String::from("<edges>\n");
+        for edge in &self.edges {
+            xml.push_str("    <edge>\n");
+            for (tag, node) in [("headnode", &edge.head), ("tailnode", &edge.tail)] {
+                xml.push_str(&format!("        <{}>\n", tag));
+                push_element(&mut xml, 3, "name", &node.name);
+                push_element(&mut xml, 3, "description", &node.description);
+                xml.push_str(&format!("        </{}>\n", tag));
+            }
+            push_element(&mut xml, 2, "datasource", &edge.datasource);
+            if edge.query.is_empty() {
+                xml.push_str("        <query/>\n");
+            } else {
+                xml.push_str(&format!(
+                    "        <query>{}</query>\n",
+                    partial_escape(&edge.query)
+                ));
+            }
+            push_element(&mut xml, 2, "key", &edge.key.to_string());
+            push_element(&mut xml, 2, "value", &edge.value.to_string());
+            xml.push_str("    </edge>\n");
+        }
+        xml.push_str("</edges>\n");
+        xml
+    }
+}
+
+/// Helper function for writing an element with its text on a line of its own, or an empty element.
+fn push_element(xml: &mut String, depth: usize, tag: &str, text: &str) {
+    let indent = "    ".repeat(depth);
+    if text.is_empty() {
+        xml.push_str(&format!("{indent}<{tag}/>\n"));
+        return;
+    }
+    xml.push_str(&format!(
+        "{indent}<{tag}>\n{indent}    {}\n{indent}</{tag}>\n",
+        partial_escape(text)
+    ));
+}
+
+/// Deserialization helper function for key and value positions
+fn deserialize_position<'de, D>(deserializer: D) -> Result<u32, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    s.trim().parse().map_err(|_| {
+        serde::de::Error::custom(format!(
+            "invalid position {:?}, expected a number",
+            s.trim()
+        ))
+    })
+}
+
+/// Helper function for loading and deserializing the DVM XML file.
+pub fn load_dvm_xml(dvm_path: &str) -> Result<Dvm> {
+    info!("Loading DVM from {}", dvm_path);
+    let xml = std::fs::read_to_string(dvm_path)
+        .with_context(|| format!("Failed to open file: {}", dvm_path))?;
+    let dvm = xml
+        .parse::<Dvm>()
+        .with_context(|| format!("Failed to parse DVM file: {}", dvm_path))?;
+    debug!(
+        "Deserialized {} edges from DVM {}",
+        dvm.edges.len(),
+        dvm_path
+    );
+    Ok(dvm)
+}
+
+impl std::str::FromStr for Dvm {
+    type Err = anyhow::Error;
+
+    /// Deserializes the edges one by one, so that errors point to the line of the offending edge.
+    fn from_str(xml: &str) -> Result<Dvm> {
+        let mut reader = quick_xml::Reader::from_str(xml);
+        let mut edges = vec![];
+        let mut in_root = false;
+        loop {
+            let start = reader.buffer_position() as usize;
+            let event = reader
+                .read_event()
+                .with_context(|| format!("Malformed XML at line {}", line_of(xml, start)))?;
+            // Skip the whitespace preceding the event, so that lines point to its tag
+            let start = start + xml[start..].len() - xml[start..].trim_start().len();
+            let line = line_of(xml, start);
+            match event {
+                Event::Start(e) if !in_root && e.name().as_ref() == b"edges" => in_root = true,
+                Event::Empty(e) if !in_root && e.name().as_ref() == b"edges" => in_root = true,
+                Event::End(e) if in_root && e.name().as_ref() == b"edges" => in_root = false,
+                Event::Start(e) if in_root && e.name().as_ref() == b"edge" => {
+                    reader
+                        .read_to_end(e.name())
+                        .with_context(|| format!("Malformed edge at line {}", line))?;
+                    let end = reader.buffer_position() as usize;
+                    edges.push(parse_edge(&xml[start..end], line)?);
+                }
+                Event::Empty(e) if in_root && e.name().as_ref() == b"edge" => {
+                    edges.push(parse_edge(
+                        &xml[start..reader.buffer_position() as usize],
+                        line,
+                    )?);
+                }
+                Event::Start(e) | Event::Empty(e) => bail!(
+                    "Unexpected element <{}> at line {}, expected <{}>",
+                    String::from_utf8_lossy(e.name().as_ref()),
+                    line,
+                    if in_root { "edge" } else { "edges" }
+                ),
+                Event::Text(e) if !e.unescape()?.trim().is_empty() => {
+                    bail!(
+                        "Unexpected text {:?} at line {}",
+                        e.unescape()?.trim(),
+                        line
+                    )
+                }
+                Event::Eof if in_root => bail!("Missing </edges> at the end of the DVM"),
+                Event::Eof => break,
+                _ => (),
+            }
+        }
+        Ok(Dvm { edges })
+    }
+}
+
+/// Helper function for deserializing a single edge found at `line`.
+fn parse_edge(xml: &str, line: usize) -> Result<DvmEdge> {
+    let edge: DvmEdge =
+        quick_xml::de::from_str(xml).with_context(|| format!("Invalid edge at line {}", line))?;
+    for (node, kind) in [(&edge.head, "headnode"), (&edge.tail, "tailnode")] {
+        if node.name.is_empty() {
+            bail!("Invalid edge at line {}: {} has an empty name", line, kind);
+        }
+    }
+    if edge.datasource.is_empty() {
+        bail!("Invalid edge at line {}: empty datasource", line);
+    }
+    trace!(
+        "Parsed edge at line {}: \"{}\" -> \"{}\"",
+        line,
+        edge.head.name,
+        edge.tail.name
+    );
+    Ok(edge)
+}
+
+/// Helper function for finding the 1-based line of a byte offset.
+fn line_of(xml: &str, offset: usize) -> usize {
+    xml[..offset.min(xml.len())]
+        .bytes()
+        .filter(|&b| b == b'\n')
+        .count()
+        + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn edge_xml(head: &str, extra: &str) -> String {
+        format!(
+            "<edge>\n<headnode><name>{}</name><description>None</description></headnode>\n\
+            <tailnode><name>b</name></tailnode>\n<datasource>ds</datasource>\n\
+            <query/><key>1</key><value>2</value>{}\n</edge>",
+            head, extra
+        )
+    }
+
+    #[test]
+    fn test_load_dvm_xml() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("test_data/example_dvm.xml");
+        let dvm = load_dvm_xml(path.to_str().unwrap()).unwrap();
+        assert_eq!(dvm.edges.len(), 4);
+        assert_eq!(
+            dvm.edges[2],
+            DvmEdge {
+                head: DvmNode {
+                    name: String::from("int_id"),
+                    description: String::from("None"),
+                },
+                tail: DvmNode {
+                    name: String::from("pickup_datetime"),
+                    description: String::from("None"),
+                },
+                datasource: String::from("mypostgresql"),
+                query: String::from(
+                    "SELECT int_id,pickup_datetime FROM trip_time WHERE int_id < 100 limit 100"
+                ),
+                key: 1,
+                value: 2,
+            }
+        );
+        assert_eq!(dvm.edges[0].query, "");
+    }
+
+    #[test]
+    fn test_dvm_to_xml_round_trips_the_example() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("test_data/example_dvm.xml");
+        let xml = std::fs::read_to_string(&path).unwrap();
+        let dvm: Dvm = xml.parse().unwrap();
+        assert_eq!(dvm.to_xml(), xml);
+    }
+
+    #[test]
+    fn test_dvm_rejects_empty_name_with_line() {
+        let xml = format!(
+            "<edges>\n{}\n{}\n</edges>",
+            edge_xml("a", ""),
+            edge_xml("", "")
+        );
+        let err = xml.parse::<Dvm>().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid edge at line 8: headnode has an empty name"
+        );
+    }
+
+    #[test]
+    fn test_dvm_rejects_unknown_element_with_line() {
+        let xml = format!("<edges>\n{}\n</edges>", edge_xml("a", "<weight>3</weight>"));
+        let err = xml.parse::<Dvm>().unwrap_err();
+        assert_eq!(err.to_string(), "Invalid edge at line 2");
+        assert!(format!("{:#}", err).contains("unknown field `weight`"));
+    }
+
+    #[test]
+    fn test_dvm_rejects_missing_element_with_line() {
+        let xml = "<edges>\n\n<edge><headnode><name>a</name></headnode></edge>\n</edges>";
+        let err = xml.parse::<Dvm>().unwrap_err();
+        assert_eq!(err.to_string(), "Invalid edge at line 3");
+        assert!(format!("{:#}", err).contains("missing field `tailnode`"));
+    }
+
+    #[test]
+    fn test_dvm_rejects_unexpected_element_between_edges() {
+        let xml = format!("<edges>\n{}\n<node/>\n</edges>", edge_xml("a", ""));
+        let err = xml.parse::<Dvm>().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unexpected element <node> at line 8, expected <edge>"
+        );
+    }
+}
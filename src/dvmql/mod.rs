@@ -4,6 +4,7 @@
 //! defining queries and the data sources to be queried.
 
 pub mod datasources;
+pub mod dvm;
 pub mod query;
 
 mod helpers;
@@ -2,8 +2,6 @@
 //!
 //! This module containts deserialization logic for the query XML file.
 
-use anyhow::Result;
-use serde::de::Deserializer;
 use serde::Deserialize;
 use std::str::FromStr;
 
@@ -24,89 +22,102 @@ pub struct Query {
 
 /// Intermediate representation of a node in the deserialized query XML file.
 #[derive(Deserialize, Debug, PartialEq)]
+#[serde(try_from = "RawNode")]
 pub struct DeserializedNode {
-    #[serde(rename = "onnode")]
     pub name: String,
     pub label: String,
-    #[serde(default, deserialize_with = "deserialize_children")]
     pub children: Vec<String>,
-    #[serde(default, deserialize_with = "deserialize_transformations")]
     pub transformations: Vec<Transformation>,
-    #[serde(default, deserialize_with = "deserialize_theta")]
     pub theta: Option<String>,
-    #[serde(default, deserialize_with = "deserialize_output")]
     pub output: bool,
 }
 
-/// Deserialization helper function for the theta
-fn deserialize_theta<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let s = String::deserialize(deserializer)?;
-    if s.is_empty() {
-        return Ok(None);
-    }
-    Ok(Some(s))
+/// Text of a node in the query XML file, parsed into a `DeserializedNode` so that
+/// errors can name the node.
+#[derive(Deserialize)]
+struct RawNode {
+    onnode: String,
+    label: String,
+    #[serde(default)]
+    children: String,
+    #[serde(default)]
+    transformations: String,
+    #[serde(default)]
+    theta: String,
+    #[serde(default)]
+    output: String,
 }
 
-/// Deserialization helper function for nodes' children
-fn deserialize_children<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let s = String::deserialize(deserializer)?;
-    if s.is_empty() {
-        return Ok(vec![]);
+impl TryFrom<RawNode> for DeserializedNode {
+    type Error = String;
+
+    fn try_from(node: RawNode) -> Result<DeserializedNode, String> {
+        let transformations = parse_transformations(&node.transformations)
+            .map_err(|e| format!("Node {}: {}", node.label, e))?;
+        let output =
+            parse_output(&node.output).map_err(|e| format!("Node {}: {}", node.label, e))?;
+        Ok(DeserializedNode {
+            name: node.onnode,
+            children: parse_children(&node.children),
+            transformations,
+            theta: Some(node.theta).filter(|theta| !theta.is_empty()),
+            output,
+            label: node.label,
+        })
     }
-    Ok(s.split(',').map(|part| part.trim().to_string()).collect())
 }
 
-/// Deserialization helper function for nodes' transformations
-fn deserialize_transformations<'de, D>(deserializer: D) -> Result<Vec<Transformation>, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let s = String::deserialize(deserializer)?;
+/// Parsing helper function for nodes' children
+fn parse_children(s: &str) -> Vec<String> {
     if s.is_empty() {
-        return Ok(vec![]);
+        return vec![];
     }
-    let transformations: Vec<Transformation> = s
-        .split(';')
-        .map(|part| {
-            let part: Vec<&str> = part.splitn(2, ':').collect();
-            let transformation = part[0].trim();
-            let args = part[1].trim();
+    s.split(',').map(|part| part.trim().to_string()).collect()
+}
 
-            let transformation: Result<Transformation> = match transformation {
-                "aggregate" => Ok(Transformation::Aggregate(
-                    AggregationType::from_str(args).expect("should be valid aggregation"),
-                )),
+/// Parsing helper function for nodes' transformations, e.g. `filter: $X001$ > 5;aggregate:sum`
+fn parse_transformations(s: &str) -> Result<Vec<Transformation>, String> {
+    s.split(';')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let (transformation, args) = part.split_once(':').ok_or_else(|| {
+                format!(
+                    "invalid transformation \"{}\", expected <transformation>: <arguments>",
+                    part
+                )
+            })?;
+            let args = args.trim();
+            match transformation.trim() {
+                "aggregate" => AggregationType::from_str(args)
+                    .map(Transformation::Aggregate)
+                    .map_err(|_| {
+                        format!(
+                            "invalid aggregation \"{}\" in transformation \"{}\", expected min, max, sum, average, count or any",
+                            args, part
+                        )
+                    }),
                 "map" => Ok(Transformation::Map(args.to_string())),
                 "filter" => Ok(Transformation::Filter(args.to_string())),
-                &_ => panic!("Invalid transformation defined"), // TODO: Handle error
-            };
-            transformation.unwrap()
+                other => Err(format!(
+                    "unknown transformation \"{}\" in \"{}\", expected filter, map or aggregate",
+                    other, part
+                )),
+            }
         })
-        .collect();
-
-    Ok(transformations)
+        .collect()
 }
 
-/// Deserialization helper function for nodes' output
-fn deserialize_output<'de, D>(deserializer: D) -> Result<bool, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let s = String::deserialize(deserializer)?;
-    if s.is_empty() {
-        return Ok(false);
-    }
-    return match s.to_lowercase().as_str() {
+/// Parsing helper function for nodes' output
+fn parse_output(s: &str) -> Result<bool, String> {
+    match s.trim().to_lowercase().as_str() {
+        "" | "no" | "false" => Ok(false),
         "yes" | "true" => Ok(true),
-        "no" | "false" => Ok(false),
-        &_ => panic!("Invalid output given"), // TODO: Handle error
-    };
+        _ => Err(format!(
+            "invalid output \"{}\", expected yes, no, true or false",
+            s.trim()
+        )),
+    }
 }
 
 #[cfg(test)]
@@ -160,4 +171,48 @@ mod tests {
         let expected_query = get_expected_query();
         assert_eq!(read_xml_file::<Query>(&path).unwrap(), expected_query)
     }
+
+    fn parse_node(node: &str) -> Result<DeserializedNode, quick_xml::DeError> {
+        quick_xml::de::from_str(&format!(
+            "<node><label>X001</label><onnode>some_node</onnode>{}</node>",
+            node
+        ))
+    }
+
+    #[test]
+    fn test_unknown_transformation_names_the_node() {
+        let err = parse_node("<transformations>filter: $X001$ > 5;sort: asc</transformations>")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Node X001: unknown transformation \"sort\" in \"sort: asc\", expected filter, map or aggregate"
+        );
+    }
+
+    #[test]
+    fn test_transformation_without_arguments_names_the_node() {
+        let err = parse_node("<transformations>aggregate</transformations>").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Node X001: invalid transformation \"aggregate\", expected <transformation>: <arguments>"
+        );
+    }
+
+    #[test]
+    fn test_unknown_aggregation_names_the_node() {
+        let err = parse_node("<transformations>aggregate: median</transformations>").unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Node X001: invalid aggregation \"median\""));
+    }
+
+    #[test]
+    fn test_invalid_output_names_the_node() {
+        let err = parse_node("<output>maybe</output>").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Node X001: invalid output \"maybe\", expected yes, no, true or false"
+        );
+        assert!(parse_node("<output>YES</output>").unwrap().output);
+    }
 }
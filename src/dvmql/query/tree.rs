@@ -2,8 +2,9 @@
 //!
 //! This module contains the logic for building the tree structure defined in a DVMQL query.
 
-use anyhow::{Context, Result};
-use std::collections::HashMap;
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use thiserror::Error;
 use tracing::trace;
 
 use super::deserialization::Query;
@@ -39,59 +40,121 @@ impl TreeNode {
     }
 }
 
+/// Error of a query whose nodes do not form a tree.
+#[derive(Debug, Error, PartialEq)]
+pub enum TreeError {
+    #[error(
+        "Incorrectly defined root node: {0}. The <rootnode> tag and <label> of a node must match."
+    )]
+    RootNotFound(String),
+    #[error("Incorrectly defined child node {child} of node {parent}. The <label> of a node must match the <children> tag.")]
+    ChildNotFound { parent: String, child: String },
+    #[error("Label {0} is used by more than one node")]
+    DuplicateLabel(String),
+    #[error("Nodes form a cycle: {}", .0.join(" -> "))]
+    Cycle(Vec<String>),
+    #[error("Node {child} is listed more than once as a child of {parent}")]
+    DuplicateChild { parent: String, child: String },
+    #[error("Node {child} is a child of both {first_parent} and {second_parent}")]
+    MultipleParents {
+        child: String,
+        first_parent: String,
+        second_parent: String,
+    },
+    #[error("Nodes {} are not reachable from the root node", .0.join(", "))]
+    Orphans(Vec<String>),
+}
+
 /// Helper function for taking a deserialized query and building the tree structure.
 pub fn build_tree(query: Query) -> Result<TreeNode> {
-    let mut deserialized_nodes_map: HashMap<String, DeserializedNode> = query
-        .nodes
-        .into_iter()
-        .map(|node| (node.label.clone(), node))
-        .collect();
+    let mut deserialized_nodes_map: HashMap<String, DeserializedNode> = HashMap::new();
+    for node in query.nodes {
+        let label = node.label.clone();
+        if deserialized_nodes_map.insert(label.clone(), node).is_some() {
+            return Err(TreeError::DuplicateLabel(label).into());
+        }
+    }
 
     let root_node = deserialized_nodes_map
         .remove(&query.root_node)
-        .with_context(|| {
-            format!(
-                "Incorrectly defined root node: {}. The <rootnode> tag and <label> of a node must match.",
-                query.root_node.clone()
-            )
-        })?;
-
-    let root_node = build_node(root_node, &mut deserialized_nodes_map)?;
+        .ok_or_else(|| TreeError::RootNotFound(query.root_node.clone()))?;
+
+    let mut builder = TreeBuilder {
+        nodes_map: deserialized_nodes_map,
+        path: vec![],
+        parents: HashMap::new(),
+    };
+    let root_node = builder.build_node(root_node)?;
+
+    if !builder.nodes_map.is_empty() {
+        let mut orphans: Vec<String> = builder.nodes_map.into_keys().collect();
+        orphans.sort();
+        return Err(TreeError::Orphans(orphans).into());
+    }
     Ok(root_node)
 }
 
-/// Helper function for building a node of the tree structure.
-fn build_node(
-    node: DeserializedNode,
-    nodes_map: &mut HashMap<String, DeserializedNode>,
-) -> Result<TreeNode> {
-    let mut tree_node = TreeNode::new(
-        node.name,
-        node.label,
-        vec![],
-        node.transformations,
-        node.theta,
-        node.output,
-    );
-    if !node.children.is_empty() {
-        let children = node
-            .children
-            .iter()
-            .map(|child_name| {
-                let child = nodes_map
-                    .remove(child_name).with_context(|| {
-                        format!(
-                            "Incorrectly defined child node: {}. The <label> of a node must match the <child> tag.",
-                            child_name.clone()
-                        )
-                    })?;
-                trace!("Building tree node {}: {}", child.name, child.label);
-                build_node(child, nodes_map)
-            })
-            .collect::<Result<Vec<TreeNode>>>()?;
-        tree_node.children = children;
+/// State of the tree while it is built from the deserialized nodes.
+struct TreeBuilder {
+    /// Nodes not yet placed in the tree, keyed by their labels.
+    nodes_map: HashMap<String, DeserializedNode>,
+    /// Labels of the nodes from the root to the node being built.
+    path: Vec<String>,
+    /// Labels of the parents of the nodes placed in the tree.
+    parents: HashMap<String, String>,
+}
+
+impl TreeBuilder {
+    /// Helper function for building a node of the tree structure.
+    fn build_node(&mut self, node: DeserializedNode) -> Result<TreeNode, TreeError> {
+        self.path.push(node.label.clone());
+        let mut children = vec![];
+        let mut seen = HashSet::new();
+        for child_label in &node.children {
+            if !seen.insert(child_label.as_str()) {
+                return Err(TreeError::DuplicateChild {
+                    parent: node.label.clone(),
+                    child: child_label.clone(),
+                });
+            }
+            let child = self.take_child(&node.label, child_label)?;
+            trace!("Building tree node {}: {}", child.name, child.label);
+            children.push(self.build_node(child)?);
+        }
+        self.path.pop();
+        Ok(TreeNode::new(
+            node.name,
+            node.label,
+            children,
+            node.transformations,
+            node.theta,
+            node.output,
+        ))
+    }
+
+    /// Helper function for taking a child out of the nodes not yet placed in the tree.
+    fn take_child(&mut self, parent: &str, child: &str) -> Result<DeserializedNode, TreeError> {
+        if let Some(node) = self.nodes_map.remove(child) {
+            self.parents.insert(child.to_string(), parent.to_string());
+            return Ok(node);
+        }
+        if let Some(start) = self.path.iter().position(|label| label == child) {
+            let mut cycle = self.path[start..].to_vec();
+            cycle.push(child.to_string());
+            return Err(TreeError::Cycle(cycle));
+        }
+        match self.parents.get(child) {
+            Some(first_parent) => Err(TreeError::MultipleParents {
+                child: child.to_string(),
+                first_parent: first_parent.clone(),
+                second_parent: parent.to_string(),
+            }),
+            None => Err(TreeError::ChildNotFound {
+                parent: parent.to_string(),
+                child: child.to_string(),
+            }),
+        }
     }
-    Ok(tree_node)
 }
 
 #[cfg(test)]
@@ -169,4 +232,125 @@ mod tests {
         assert_eq!(node3.theta, None);
         assert!(node3.output);
     }
+
+    fn leaf(label: &str, children: &[&str]) -> DeserializedNode {
+        DeserializedNode {
+            name: format!("node_{}", label),
+            label: label.to_string(),
+            children: children.iter().map(|child| child.to_string()).collect(),
+            transformations: vec![],
+            theta: None,
+            output: false,
+        }
+    }
+
+    fn tree_error(nodes: Vec<DeserializedNode>) -> TreeError {
+        let query = Query {
+            root_node: "X000".to_string(),
+            nodes,
+        };
+        build_tree(query)
+            .err()
+            .expect("tree should be invalid")
+            .downcast()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_build_tree_detects_missing_root() {
+        assert_eq!(
+            tree_error(vec![leaf("X001", &[])]),
+            TreeError::RootNotFound("X000".to_string())
+        );
+    }
+
+    #[test]
+    fn test_build_tree_detects_missing_child() {
+        assert_eq!(
+            tree_error(vec![leaf("X000", &["X001"])]),
+            TreeError::ChildNotFound {
+                parent: "X000".to_string(),
+                child: "X001".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_build_tree_detects_duplicate_labels() {
+        assert_eq!(
+            tree_error(vec![
+                leaf("X000", &["X001"]),
+                leaf("X001", &[]),
+                leaf("X001", &[])
+            ]),
+            TreeError::DuplicateLabel("X001".to_string())
+        );
+    }
+
+    #[test]
+    fn test_build_tree_detects_cycles() {
+        let error = tree_error(vec![
+            leaf("X000", &["X001"]),
+            leaf("X001", &["X002"]),
+            leaf("X002", &["X001"]),
+        ]);
+        assert_eq!(
+            error,
+            TreeError::Cycle(vec![
+                "X001".to_string(),
+                "X002".to_string(),
+                "X001".to_string()
+            ])
+        );
+        assert_eq!(
+            error.to_string(),
+            "Nodes form a cycle: X001 -> X002 -> X001"
+        );
+    }
+
+    #[test]
+    fn test_build_tree_detects_multiple_parents() {
+        assert_eq!(
+            tree_error(vec![
+                leaf("X000", &["X001", "X002"]),
+                leaf("X001", &["X003"]),
+                leaf("X002", &["X003"]),
+                leaf("X003", &[]),
+            ]),
+            TreeError::MultipleParents {
+                child: "X003".to_string(),
+                first_parent: "X001".to_string(),
+                second_parent: "X002".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_build_tree_detects_duplicate_children() {
+        let error = tree_error(vec![leaf("X000", &["X001", "X001"]), leaf("X001", &[])]);
+        assert_eq!(
+            error,
+            TreeError::DuplicateChild {
+                parent: "X000".to_string(),
+                child: "X001".to_string()
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "Node X001 is listed more than once as a child of X000"
+        );
+    }
+
+    #[test]
+    fn test_build_tree_detects_orphans() {
+        assert_eq!(
+            tree_error(vec![
+                leaf("X000", &["X001"]),
+                leaf("X001", &[]),
+                leaf("X003", &[]),
+                leaf("X002", &[]),
+            ]),
+            TreeError::Orphans(vec!["X002".to_string(), "X003".to_string()])
+        );
+    }
 }
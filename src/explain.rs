@@ -0,0 +1,303 @@
+//! # Explain
+//!
+//! This module contains the logic for describing how a query would be executed,
+//! resolving the edges of the query tree from the DVM without reading any data.
+
+use std::{collections::HashMap, fmt};
+
+use anyhow::Result;
+use serde::Serialize;
+use strum::EnumString;
+
+use crate::dvmql::query::tree::TreeNode;
+use crate::load::Datasource;
+use crate::store::DvmStore;
+use crate::transform::Transformation;
+
+/// Format in which the plan of a query is printed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, EnumString)]
+#[strum(serialize_all = "UPPERCASE", ascii_case_insensitive)]
+pub enum ExplainFormat {
+    /// Indented tree of the nodes, with their edges and transformations.
+    #[default]
+    Text,
+    /// JSON document of the plan nodes.
+    Json,
+}
+
+/// Node of the query tree, along with the edges its values are read through.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct PlanNode {
+    pub label: String,
+    pub attribute: String,
+    /// Edges from the parent node, empty for the root node or when the DVM has none.
+    pub edges: Vec<PlanEdge>,
+    /// Transformations, in the order they are applied.
+    pub transformations: Vec<PlanTransformation>,
+    pub theta: Option<String>,
+    pub output: bool,
+    pub children: Vec<PlanNode>,
+}
+
+/// Transformation of a node, split into its kind and expression.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct PlanTransformation {
+    /// One of `filter`, `map` or `aggregate`.
+    pub kind: &'static str,
+    /// Condition of a filter, expression of a map, or aggregation type.
+    pub expression: String,
+}
+
+/// Edge of the DVM that a node of the query tree is read through.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct PlanEdge {
+    pub datasource: String,
+    /// Type of the datasource, or `None` if it is not defined in the datasources file.
+    pub datasource_type: Option<&'static str>,
+    pub query: Option<String>,
+    pub key: u32,
+    pub value: u32,
+}
+
+/// Resolves the edges of the query tree rooted at `root` into its execution plan.
+pub async fn explain(
+    root: &TreeNode,
+    datasources: &HashMap<String, Datasource>,
+    store: &dyn DvmStore,
+) -> Result<PlanNode> {
+    let mut plan = plan_node(root);
+    // Walk the tree and the plan side by side, resolving the edges of every child
+    let mut stack: Vec<(&TreeNode, &mut PlanNode)> = vec![(root, &mut plan)];
+    while let Some((node, plan_node)) = stack.pop() {
+        for (child, plan_child) in node.children.iter().zip(plan_node.children.iter_mut()) {
+            plan_child.edges = store
+                .edges(&node.name, &child.name)
+                .await?
+                .into_iter()
+                .map(|edge| PlanEdge {
+                    datasource_type: datasources.get(&edge.datasource_name).map(datasource_type),
+                    datasource: edge.datasource_name,
+                    query: edge.query,
+                    key: edge.key_pos,
+                    value: edge.value_pos,
+                })
+                .collect();
+            stack.push((child, plan_child));
+        }
+    }
+    Ok(plan)
+}
+
+/// Helper function for describing a node and its descendants, without their edges.
+fn plan_node(node: &TreeNode) -> PlanNode {
+    PlanNode {
+        label: node.label.clone(),
+        attribute: node.name.clone(),
+        edges: vec![],
+        transformations: node
+            .transformations
+            .iter()
+            .map(plan_transformation)
+            .collect(),
+        theta: node.theta.clone(),
+        output: node.output,
+        children: node.children.iter().map(plan_node).collect(),
+    }
+}
+
+/// Helper function for describing a transformation by its kind and expression.
+fn plan_transformation(transformation: &Transformation) -> PlanTransformation {
+    let (kind, expression) = match transformation {
+        Transformation::Filter(filter) => ("filter", filter.clone()),
+        Transformation::Map(map) => ("map", map.clone()),
+        Transformation::Aggregate(aggregation) => ("aggregate", aggregation.to_string()),
+    };
+    PlanTransformation { kind, expression }
+}
+
+/// Helper function for naming the type of a datasource like the datasources XML file.
+fn datasource_type(datasource: &Datasource) -> &'static str {
+    match datasource {
+        Datasource::Csv(_) => "csv",
+        Datasource::Xml(_) => "xml",
+        Datasource::Excel(_) => "excel",
+        Datasource::Database(_) => "db",
+    }
+}
+
+impl fmt::Display for PlanNode {
+    /// Formats the plan as a text tree.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_tree(f, "", "", true)
+    }
+}
+
+impl fmt::Display for PlanTransformation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.kind, self.expression)
+    }
+}
+
+impl PlanNode {
+    /// Helper function for formatting a node with the given prefixes, followed by its children.
+    fn fmt_tree(
+        &self,
+        f: &mut fmt::Formatter,
+        prefix: &str,
+        branch: &str,
+        is_root: bool,
+    ) -> fmt::Result {
+        write!(f, "{}{}{} ({})", prefix, branch, self.label, self.attribute)?;
+        if self.output {
+            write!(f, " [output]")?;
+        }
+        writeln!(f)?;
+
+        let details_prefix = match (is_root, branch) {
+            (true, _) => prefix.to_string(),
+            (false, "└── ") => format!("{}    ", prefix),
+            (false, _) => format!("{}│   ", prefix),
+        };
+        let indent = if self.children.is_empty() {
+            format!("{}  ", details_prefix)
+        } else {
+            format!("{}│ ", details_prefix)
+        };
+        if !is_root && self.edges.is_empty() {
+            writeln!(f, "{}edge: none found in the DVM", indent)?;
+        }
+        for edge in &self.edges {
+            write!(f, "{}edge: {}", indent, edge.datasource)?;
+            match edge.datasource_type {
+                Some(ds_type) => write!(f, " ({})", ds_type)?,
+                None => write!(f, " (undefined)")?,
+            }
+            writeln!(f, ", key {}, value {}", edge.key, edge.value)?;
+            if let Some(query) = edge.query.as_deref().filter(|query| !query.is_empty()) {
+                writeln!(f, "{}  query: {}", indent, query)?;
+            }
+        }
+        for (i, transformation) in self.transformations.iter().enumerate() {
+            writeln!(f, "{}{}. {}", indent, i + 1, transformation)?;
+        }
+        if let Some(theta) = &self.theta {
+            writeln!(f, "{}theta: {}", indent, theta)?;
+        }
+
+        for (i, child) in self.children.iter().enumerate() {
+            let branch = if i + 1 == self.children.len() {
+                "└── "
+            } else {
+                "├── "
+            };
+            child.fmt_tree(f, &details_prefix, branch, false)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryStore;
+    use crate::test_util::{csv_datasource, dvm, node};
+    use crate::transform::aggregate::AggregationType;
+
+    async fn get_plan() -> PlanNode {
+        let dvm = dvm(&[
+            ("id", "firstname", "myCSV", "", 1, 2),
+            (
+                "firstname",
+                "email",
+                "myDb",
+                "SELECT name, email FROM users",
+                1,
+                2,
+            ),
+        ]);
+        let datasources = HashMap::from([(
+            String::from("myCSV"),
+            csv_datasource("myCSV", "example_csv.csv"),
+        )]);
+        let mut firstname = node("firstname", "X001", vec![node("email", "X002", vec![])]);
+        firstname.transformations = vec![
+            Transformation::Filter(String::from("$X001$ != ''")),
+            Transformation::Aggregate(AggregationType::Count),
+        ];
+        let mut root = node("id", "X000", vec![firstname, node("phone", "X003", vec![])]);
+        root.output = false;
+        root.theta = Some(String::from("$X002$ != ''"));
+
+        explain(&root, &datasources, &MemoryStore::from(dvm))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_explain_resolves_edges() {
+        let plan = get_plan().await;
+        assert!(plan.edges.is_empty());
+        let firstname = &plan.children[0];
+        assert_eq!(
+            firstname.edges,
+            vec![PlanEdge {
+                datasource: String::from("myCSV"),
+                datasource_type: Some("csv"),
+                query: None,
+                key: 1,
+                value: 2,
+            }]
+        );
+        assert_eq!(
+            firstname.transformations,
+            vec![
+                PlanTransformation {
+                    kind: "filter",
+                    expression: String::from("$X001$ != ''"),
+                },
+                PlanTransformation {
+                    kind: "aggregate",
+                    expression: String::from("count"),
+                },
+            ]
+        );
+        assert_eq!(firstname.children[0].edges[0].datasource_type, None);
+        assert!(plan.children[1].edges.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_explain_text_tree() {
+        let plan = get_plan().await;
+        assert_eq!(
+            plan.to_string(),
+            "X000 (id)\n\
+            │ theta: $X002$ != ''\n\
+            ├── X001 (firstname) [output]\n\
+            │   │ edge: myCSV (csv), key 1, value 2\n\
+            │   │ 1. filter: $X001$ != ''\n\
+            │   │ 2. aggregate: count\n\
+            │   └── X002 (email) [output]\n\
+            │         edge: myDb (undefined), key 1, value 2\n\
+            │           query: SELECT name, email FROM users\n\
+            └── X003 (phone) [output]\n\
+            \x20     edge: none found in the DVM\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_explain_json() {
+        let plan = get_plan().await;
+        let json: serde_json::Value = serde_json::to_value(&plan).unwrap();
+        assert_eq!(json["label"], "X000");
+        assert_eq!(json["theta"], "$X002$ != ''");
+        assert_eq!(json["children"][0]["edges"][0]["datasource"], "myCSV");
+        assert_eq!(
+            json["children"][0]["transformations"][1],
+            serde_json::json!({"kind": "aggregate", "expression": "count"})
+        );
+        assert_eq!(
+            json["children"][0]["children"][0]["edges"][0]["query"],
+            "SELECT name, email FROM users"
+        );
+    }
+}
@@ -0,0 +1,475 @@
+//! # Join
+//!
+//! This module contains the logic for combining the results of a node's children
+//! into multi-column rows keyed by the values of the node.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use anyhow::{bail, Context, Result};
+use strum::EnumString;
+
+use crate::transform::expression::{Bindings, Expression};
+
+/// Default limit on the number of value combinations a theta is evaluated on for a single row.
+pub const DEFAULT_MAX_THETA_COMBINATIONS: usize = 1_000_000;
+
+/// Mode used when joining the results of a node's children.
+#[derive(Debug, Default, Clone, Copy, PartialEq, EnumString)]
+#[strum(serialize_all = "UPPERCASE", ascii_case_insensitive)]
+pub enum JoinMode {
+    /// Keep every key, leaving the columns of children without values empty.
+    #[default]
+    All,
+    /// Keep only the keys present in every child.
+    Intersect,
+}
+
+/// Multi-column rows produced for a node of the query tree.
+#[derive(Debug, Default, PartialEq)]
+pub struct ResultTable {
+    /// Labels of the descendant nodes, in tree order.
+    pub columns: Vec<String>,
+    /// Rows keyed by the values of the node, holding the value list of every column.
+    ///
+    /// An empty value list stands for a missing (null) value.
+    pub rows: BTreeMap<String, Vec<Vec<String>>>,
+}
+
+/// Data materialized for a child node of the query tree.
+#[derive(Debug, Default, PartialEq)]
+pub struct NodeResult {
+    pub label: String,
+    /// Values of the node, keyed by the values of its parent node.
+    pub values: HashMap<String, Vec<String>>,
+    /// Joined rows of the node's own children, keyed by the values of the node.
+    pub table: ResultTable,
+}
+
+/// Joins the results of a node's children on the values of the node.
+pub fn join(children: Vec<NodeResult>, mode: JoinMode) -> ResultTable {
+    let mut columns = vec![];
+    let mut lifted = vec![];
+    for child in children {
+        columns.push(child.label.clone());
+        columns.extend(child.table.columns.iter().cloned());
+        lifted.push(lift(child, mode));
+    }
+
+    let mut keys: BTreeSet<&String> = BTreeSet::new();
+    for (i, (_, rows)) in lifted.iter().enumerate() {
+        match mode {
+            JoinMode::All => keys.extend(rows.keys()),
+            JoinMode::Intersect if i == 0 => keys.extend(rows.keys()),
+            JoinMode::Intersect => keys.retain(|key| rows.contains_key(*key)),
+        }
+    }
+
+    let rows = keys
+        .into_iter()
+        .map(|key| {
+            let row = lifted
+                .iter()
+                .flat_map(|(width, rows)| {
+                    rows.get(key)
+                        .cloned()
+                        .unwrap_or_else(|| vec![vec![]; *width])
+                })
+                .collect();
+            (key.clone(), row)
+        })
+        .collect();
+
+    ResultTable { columns, rows }
+}
+
+/// Keeps the keys of a node, and the values of its children under them, that satisfy the theta
+/// condition.
+///
+/// The condition can refer to the node by `label`, bound to each key, and to its direct children,
+/// bound to their values under that key. It is evaluated on every combination of the values of
+/// the referenced children: values that take part in no satisfying combination are dropped, and
+/// so are keys left without any satisfying combination, including keys missing values for a
+/// referenced child. It is applied before joining, so that the descendants of a dropped value are
+/// dropped along with it. In INTERSECT mode, the values that joining drops for lacking rows of
+/// their own are dropped first.
+///
+/// Keys with more than `max_combinations` combinations fail the query instead of being evaluated,
+/// leaving the children unchanged.
+pub fn apply_theta(
+    children: &mut [NodeResult],
+    label: &str,
+    theta: &Expression,
+    mode: JoinMode,
+    max_combinations: usize,
+) -> Result<()> {
+    let mut indices: Vec<usize> = vec![];
+    for reference in theta.references() {
+        if reference == label {
+            continue;
+        }
+        let index = children
+            .iter()
+            .position(|child| child.label == reference)
+            .with_context(|| {
+                format!(
+                    "Theta of node {} refers to ${}$, which is neither the node nor one of its children",
+                    label, reference
+                )
+            })?;
+        if !indices.contains(&index) {
+            indices.push(index);
+        }
+    }
+    if mode == JoinMode::Intersect {
+        children.iter_mut().for_each(retain_joined_values);
+    }
+
+    // Evaluate every key before dropping anything, so that errors leave the children unchanged
+    let keys: BTreeSet<String> = children
+        .iter()
+        .flat_map(|child| child.values.keys().cloned())
+        .collect();
+    let mut kept = HashMap::new();
+    for key in keys {
+        let values: Vec<&[String]> = indices
+            .iter()
+            .map(|&i| children[i].values.get(&key).map_or(&[][..], Vec::as_slice))
+            .collect();
+        let satisfied = theta_key(
+            theta,
+            label,
+            &key,
+            &indices,
+            children,
+            &values,
+            max_combinations,
+        )?;
+        kept.insert(key, satisfied);
+    }
+
+    for (j, &i) in indices.iter().enumerate() {
+        for (key, values) in children[i].values.iter_mut() {
+            if let Some(Some(satisfied)) = kept.get(key) {
+                let mut keep = satisfied[j].iter();
+                values.retain(|_| keep.next().copied().unwrap_or(false));
+            }
+        }
+    }
+    for child in children.iter_mut() {
+        child
+            .values
+            .retain(|key, _| matches!(kept.get(key), Some(Some(_))));
+    }
+    Ok(())
+}
+
+/// Helper function for evaluating the theta condition on the values of the referenced children
+/// under a key.
+///
+/// Returns which values take part in a satisfying combination, or `None` to drop the key.
+fn theta_key(
+    theta: &Expression,
+    label: &str,
+    key: &str,
+    indices: &[usize],
+    children: &[NodeResult],
+    values: &[&[String]],
+    max_combinations: usize,
+) -> Result<Option<Vec<Vec<bool>>>> {
+    if values.iter().any(|values| values.is_empty()) {
+        return Ok(None);
+    }
+    let combinations = values
+        .iter()
+        .try_fold(1usize, |product, values| product.checked_mul(values.len()));
+    if combinations.is_none_or(|combinations| combinations > max_combinations) {
+        bail!(
+            "Theta of node {} has more than {} combinations of values to evaluate for key {}",
+            label,
+            max_combinations,
+            key
+        );
+    }
+    let mut satisfied: Vec<Vec<bool>> = values
+        .iter()
+        .map(|values| vec![false; values.len()])
+        .collect();
+    let mut any_satisfied = false;
+    // Walk every combination of the referenced values, like an odometer
+    let mut counters = vec![0; values.len()];
+    'combinations: loop {
+        let mut bindings = Bindings::from([(label, key)]);
+        for (j, &i) in indices.iter().enumerate() {
+            bindings.insert(children[i].label.as_str(), values[j][counters[j]].as_str());
+        }
+        if theta.evaluate_condition(&bindings)? {
+            any_satisfied = true;
+            for (j, &counter) in counters.iter().enumerate() {
+                satisfied[j][counter] = true;
+            }
+        }
+        let mut j = 0;
+        loop {
+            if j == counters.len() {
+                break 'combinations;
+            }
+            counters[j] += 1;
+            if counters[j] < values[j].len() {
+                break;
+            }
+            counters[j] = 0;
+            j += 1;
+        }
+    }
+    Ok(any_satisfied.then_some(satisfied))
+}
+
+/// Helper function for dropping the values of a child that have no rows of their own
+/// descendants, along with the keys left without values.
+fn retain_joined_values(child: &mut NodeResult) {
+    if child.table.columns.is_empty() {
+        return;
+    }
+    let rows = &child.table.rows;
+    child.values.retain(|_, values| {
+        values.retain(|value| rows.contains_key(value));
+        !values.is_empty()
+    });
+}
+
+/// Helper function for re-keying a child's column and its descendant columns
+/// on the values of the parent node.
+///
+/// Returns the number of columns and the re-keyed rows.
+fn lift(child: NodeResult, mode: JoinMode) -> (usize, HashMap<String, Vec<Vec<String>>>) {
+    let width = 1 + child.table.columns.len();
+    let has_descendants = !child.table.columns.is_empty();
+    let rows = child
+        .values
+        .into_iter()
+        .filter_map(|(key, values)| {
+            let mut row = vec![vec![]; width];
+            for value in values {
+                match child.table.rows.get(&value) {
+                    Some(descendants) => {
+                        for (column, descendant) in row[1..].iter_mut().zip(descendants) {
+                            column.extend(descendant.iter().cloned());
+                        }
+                    }
+                    None if mode == JoinMode::Intersect && has_descendants => continue,
+                    None => (),
+                }
+                row[0].push(value);
+            }
+            if row[0].is_empty() {
+                return None;
+            }
+            Some((key, row))
+        })
+        .collect();
+    (width, rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    fn leaf(label: &str, values: &[(&str, &[&str])]) -> NodeResult {
+        NodeResult {
+            label: label.to_string(),
+            values: values
+                .iter()
+                .map(|(key, values)| (key.to_string(), strings(values)))
+                .collect(),
+            table: ResultTable::default(),
+        }
+    }
+
+    #[test]
+    fn test_join_all_keeps_every_key() {
+        let children = vec![
+            leaf("X001", &[("1", &["a"]), ("2", &["b", "c"])]),
+            leaf("X002", &[("2", &["x"]), ("3", &["y"])]),
+        ];
+        let table = join(children, JoinMode::All);
+        assert_eq!(table.columns, strings(&["X001", "X002"]));
+        assert_eq!(table.rows.len(), 3);
+        assert_eq!(table.rows["1"], vec![strings(&["a"]), vec![]]);
+        assert_eq!(table.rows["2"], vec![strings(&["b", "c"]), strings(&["x"])]);
+        assert_eq!(table.rows["3"], vec![vec![], strings(&["y"])]);
+    }
+
+    #[test]
+    fn test_join_intersect_keeps_common_keys() {
+        let children = vec![
+            leaf("X001", &[("1", &["a"]), ("2", &["b", "c"])]),
+            leaf("X002", &[("2", &["x"]), ("3", &["y"])]),
+        ];
+        let table = join(children, JoinMode::Intersect);
+        assert_eq!(table.rows.len(), 1);
+        assert_eq!(table.rows["2"], vec![strings(&["b", "c"]), strings(&["x"])]);
+    }
+
+    #[test]
+    fn test_join_lifts_descendant_columns() {
+        let grandchild = leaf("X002", &[("a", &["g1"]), ("b", &["g2", "g3"])]);
+        let mut child = leaf("X001", &[("1", &["a", "b"]), ("2", &["c"])]);
+        child.table = join(vec![grandchild], JoinMode::All);
+
+        let table = join(vec![child], JoinMode::All);
+        assert_eq!(table.columns, strings(&["X001", "X002"]));
+        assert_eq!(
+            table.rows["1"],
+            vec![strings(&["a", "b"]), strings(&["g1", "g2", "g3"])]
+        );
+        assert_eq!(table.rows["2"], vec![strings(&["c"]), vec![]]);
+    }
+
+    #[test]
+    fn test_join_intersect_drops_values_without_descendants() {
+        let grandchild = leaf("X002", &[("a", &["g1"])]);
+        let mut child = leaf("X001", &[("1", &["a", "b"]), ("2", &["c"])]);
+        child.table = join(vec![grandchild], JoinMode::Intersect);
+
+        let table = join(vec![child], JoinMode::Intersect);
+        assert_eq!(table.rows.len(), 1);
+        assert_eq!(table.rows["1"], vec![strings(&["a"]), strings(&["g1"])]);
+    }
+
+    #[test]
+    fn test_apply_theta_between_children() {
+        let mut children = vec![
+            leaf("X001", &[("1", &["5", "10"]), ("2", &["1"]), ("3", &["7"])]),
+            leaf("X002", &[("1", &["6"]), ("2", &["3"])]),
+        ];
+        let theta: Expression = "$X001$ > $X002$".parse().unwrap();
+        apply_theta(
+            &mut children,
+            "X000",
+            &theta,
+            JoinMode::All,
+            DEFAULT_MAX_THETA_COMBINATIONS,
+        )
+        .unwrap();
+        let table = join(children, JoinMode::All);
+        assert_eq!(table.rows.len(), 1);
+        assert_eq!(table.rows["1"], vec![strings(&["10"]), strings(&["6"])]);
+    }
+
+    #[test]
+    fn test_apply_theta_between_parent_and_child() {
+        let mut children = vec![leaf("X001", &[("5", &["3", "8"]), ("6", &["1"])])];
+        let theta: Expression = "$X000$ < $X001$".parse().unwrap();
+        apply_theta(
+            &mut children,
+            "X000",
+            &theta,
+            JoinMode::All,
+            DEFAULT_MAX_THETA_COMBINATIONS,
+        )
+        .unwrap();
+        let table = join(children, JoinMode::All);
+        assert_eq!(table.rows.len(), 1);
+        assert_eq!(table.rows["5"], vec![strings(&["8"])]);
+    }
+
+    #[test]
+    fn test_apply_theta_with_unknown_reference() {
+        let mut children = vec![leaf("X001", &[("1", &["a"])])];
+        let theta: Expression = "$X001$ = $X009$".parse().unwrap();
+        assert!(apply_theta(
+            &mut children,
+            "X000",
+            &theta,
+            JoinMode::All,
+            DEFAULT_MAX_THETA_COMBINATIONS
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_apply_theta_drops_descendants_of_dropped_values() {
+        let mut x001 = leaf("X001", &[("1", &["a", "b"])]);
+        x001.table = join(
+            vec![leaf("X002", &[("a", &["red"]), ("b", &["blue"])])],
+            JoinMode::All,
+        );
+        let mut children = vec![x001];
+        let theta: Expression = "$X001$ = 'a'".parse().unwrap();
+        apply_theta(
+            &mut children,
+            "X000",
+            &theta,
+            JoinMode::All,
+            DEFAULT_MAX_THETA_COMBINATIONS,
+        )
+        .unwrap();
+        let table = join(children, JoinMode::All);
+        assert_eq!(table.rows["1"], vec![strings(&["a"]), strings(&["red"])]);
+    }
+
+    #[test]
+    fn test_apply_theta_rejects_grandchild_reference() {
+        let mut x001 = leaf("X001", &[("1", &["a", "b"])]);
+        x001.table = join(
+            vec![leaf("X002", &[("a", &["red"]), ("b", &["blue"])])],
+            JoinMode::All,
+        );
+        let mut children = vec![x001];
+        let theta: Expression = "$X001$ = 'a' and $X002$ = 'blue'".parse().unwrap();
+        let err = apply_theta(
+            &mut children,
+            "X000",
+            &theta,
+            JoinMode::All,
+            DEFAULT_MAX_THETA_COMBINATIONS,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Theta of node X000 refers to $X002$, which is neither the node nor one of its children"
+        );
+    }
+
+    #[test]
+    fn test_apply_theta_limits_combinations() {
+        let children = || {
+            vec![
+                leaf("X001", &[("1", &["5", "10"]), ("2", &["1"])]),
+                leaf("X002", &[("1", &["6", "7"]), ("2", &["3"])]),
+            ]
+        };
+        let theta: Expression = "$X001$ > $X002$".parse().unwrap();
+        let mut limited = children();
+        let err = apply_theta(&mut limited, "X000", &theta, JoinMode::All, 3).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Theta of node X000 has more than 3 combinations of values to evaluate for key 1"
+        );
+        assert_eq!(limited, children());
+
+        let mut children = children();
+        apply_theta(&mut children, "X000", &theta, JoinMode::All, 4).unwrap();
+        let table = join(children, JoinMode::All);
+        assert_eq!(table.rows.len(), 1);
+        assert_eq!(
+            table.rows["1"],
+            vec![strings(&["10"]), strings(&["6", "7"])]
+        );
+    }
+
+    #[test]
+    fn test_join_mode_from_str() {
+        use std::str::FromStr;
+        assert_eq!(JoinMode::from_str("ALL").unwrap(), JoinMode::All);
+        assert_eq!(
+            JoinMode::from_str("intersect").unwrap(),
+            JoinMode::Intersect
+        );
+        assert!(JoinMode::from_str("NONE").is_err());
+    }
+}
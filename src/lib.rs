@@ -1,45 +1,248 @@
 pub mod dvmql;
+pub mod explain;
+pub mod join;
 pub mod load;
+pub mod neo4j;
+pub mod output;
+pub mod store;
+#[cfg(test)]
+mod test_util;
 pub mod transform;
+pub mod validate;
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use async_recursion::async_recursion;
-use neo4rs::{query, Graph};
 use std::collections::HashMap;
-use tracing::trace;
+use tokio_stream::StreamExt;
+use tracing::{debug, error, trace};
 
 use dvmql::query::tree::TreeNode;
-use load::Datasource;
+use join::{apply_theta, join, JoinMode, NodeResult, ResultTable};
+use load::{ConnectionPools, Datasource};
+use store::DvmStore;
+use transform::{
+    apply_root_filters, apply_transformations, expression::Expression, unsupported_transformation,
+};
 
-use crate::load::edges::Edge;
-
-const QUERY: &str = "MATCH (a:attribute{name: $nodeA})-[r:has]->(b:attribute{name: $nodeB}) RETURN r.datasource as datasource, r.query as query, r.key as key, r.value as value";
+/// Executes the query tree rooted at `root`, returning its rows keyed by the values of the root.
+///
+/// Fails before reading any data if a node has a transformation that cannot be applied.
+/// The filters of the root node are applied to the keys of its rows.
+pub async fn dfs(
+    root: &TreeNode,
+    store: &dyn DvmStore,
+    datasources: &HashMap<String, Datasource>,
+    pools: &ConnectionPools,
+    mode: JoinMode,
+    max_theta_combinations: usize,
+) -> Result<ResultTable> {
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+        if let Some(problem) = unsupported_transformation(node, std::ptr::eq(node, root)) {
+            bail!(problem);
+        }
+        stack.extend(&node.children);
+    }
+    let mut table = dfs_node(
+        root,
+        store,
+        datasources,
+        pools,
+        mode,
+        max_theta_combinations,
+    )
+    .await?;
+    apply_root_filters(&root.transformations, &root.label, &mut table.rows)?;
+    Ok(table)
+}
 
+/// Helper function for executing the subtree rooted at `node`.
 #[async_recursion]
-pub async fn dfs(
+async fn dfs_node(
     node: &TreeNode,
-    graph: &Graph,
+    store: &dyn DvmStore,
     datasources: &HashMap<String, Datasource>,
-) -> Result<()> {
+    pools: &ConnectionPools,
+    mode: JoinMode,
+    max_theta_combinations: usize,
+) -> Result<ResultTable> {
+    let mut children = vec![];
     for child in &node.children {
-        dfs(child, graph, datasources).await?;
-
-        let mut result = graph
-            .execute(
-                query(QUERY)
-                    .param("nodeA", child.name.as_str())
-                    .param("nodeB", node.name.as_str()),
+        let mut child_result = NodeResult {
+            label: child.label.clone(),
+            table: dfs_node(
+                child,
+                store,
+                datasources,
+                pools,
+                mode,
+                max_theta_combinations,
             )
-            .await?;
-        while let Some(r) = result.next().await? {
-            let datasource: String = r.to::<Edge>()?.datasource_name;
-            let key: u32 = r.to::<Edge>()?.key_pos;
-            let value: u32 = r.to::<Edge>()?.value_pos;
-            let dt = datasources.get(&datasource).with_context(|| {
-                format!("Datasource {} not found in datasources list", &datasource)
+            .await?,
+            ..Default::default()
+        };
+
+        let edges = store.edges(&node.name, &child.name).await?;
+        for edge in &edges {
+            let dt = datasources.get(&edge.datasource_name).with_context(|| {
+                format!(
+                    "Datasource {} not found in datasources list",
+                    &edge.datasource_name
+                )
             })?;
-            trace!("Edge {} => {}", &child.label, &node.label);
+            trace!("Edge {} => {}", &node.label, &child.label);
+            let mut records = dt
+                .read_async(edge, pools)
+                .await
+                .with_context(|| format!("Failed to read datasource {}", &edge.datasource_name))?;
+            while let Some(record) = records.next().await {
+                let (key, value) = record?;
+                child_result.values.entry(key).or_default().push(value);
+            }
         }
+        if edges.is_empty() {
+            bail!(
+                "No edge found between {} ({}) and {} ({})",
+                &node.label,
+                &node.name,
+                &child.label,
+                &child.name
+            );
+        }
+        let failures = apply_transformations(
+            &child.transformations,
+            &child.label,
+            &node.label,
+            &mut child_result.values,
+        )?;
+        for (key, e) in &failures {
+            error!("Dropped key {} of node {}: {:#}", key, &child.label, e);
+        }
+        debug!(
+            "Materialized {} keys for node {}",
+            child_result.values.len(),
+            &child.label
+        );
+        children.push(child_result);
+    }
+    if let Some(theta) = &node.theta {
+        let theta: Expression = theta.parse()?;
+        apply_theta(
+            &mut children,
+            &node.label,
+            &theta,
+            mode,
+            max_theta_combinations,
+        )
+        .with_context(|| format!("Failed to apply theta of node {}", &node.label))?;
+    }
+    let table = join(children, mode);
+    debug!("Joined {} rows for node {}", table.rows.len(), &node.label);
+    Ok(table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::join::DEFAULT_MAX_THETA_COMBINATIONS;
+    use crate::store::MemoryStore;
+    use crate::test_util::{csv_datasource, dvm, node};
+    use crate::transform::{aggregate::AggregationType, Transformation};
+
+    fn store() -> MemoryStore {
+        MemoryStore::from(dvm(&[
+            ("id", "firstname", "myCSV", "", 1, 2),
+            ("id", "lastname", "myCSV", "", 1, 3),
+            ("firstname", "email", "myCSV", "", 2, 4),
+        ]))
+    }
+
+    fn datasources() -> HashMap<String, Datasource> {
+        HashMap::from([(
+            String::from("myCSV"),
+            csv_datasource("myCSV", "example_csv.csv"),
+        )])
+    }
+
+    async fn run(root: &TreeNode) -> Result<ResultTable> {
+        dfs(
+            root,
+            &store(),
+            &datasources(),
+            &ConnectionPools::default(),
+            JoinMode::All,
+            DEFAULT_MAX_THETA_COMBINATIONS,
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_dfs_with_memory_store() {
+        let mut root = node("id", "X000", vec![]);
+        root.children = vec![
+            node("firstname", "X001", vec![]),
+            node("lastname", "X002", vec![]),
+        ];
+
+        let table = run(&root).await.unwrap();
+        assert_eq!(table.columns, vec!["X001", "X002"]);
+        assert_eq!(
+            table.rows["100"],
+            vec![vec![String::from("Georgina")], vec![String::from("Hull")]]
+        );
+
+        root.children.push(node("email", "X003", vec![]));
+        assert!(run(&root).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_dfs_filters_root_keys() {
+        let mut root = node("id", "X000", vec![]);
+        root.children = vec![node("firstname", "X001", vec![])];
+        root.transformations = vec![Transformation::Filter(String::from("$X000$ = 100"))];
+
+        let table = run(&root).await.unwrap();
+        assert_eq!(table.rows.keys().collect::<Vec<_>>(), vec!["100"]);
+
+        root.transformations = vec![Transformation::Map(String::from("$X000$ + 1"))];
+        let err = run(&root).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Node X000: map \"$X000$ + 1\" is not supported on the root node"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dfs_lifts_grandchildren() {
+        let mut firstname = node("firstname", "X001", vec![]);
+        firstname.children = vec![node("email", "X002", vec![])];
+        firstname.transformations = vec![Transformation::Filter(String::from("$X001$ != 'Lila'"))];
+        let mut root = node("id", "X000", vec![]);
+        root.children = vec![firstname];
+
+        let table = run(&root).await.unwrap();
+        assert_eq!(table.columns, vec!["X001", "X002"]);
+        assert_eq!(
+            table.rows["100"],
+            vec![
+                vec![String::from("Georgina")],
+                vec![String::from("Georgina.Hull@yopmail.com")]
+            ]
+        );
+        assert!(!table.rows.contains_key("101"));
+
+        root.children[0].transformations = vec![Transformation::Map(String::from("upper($X001$)"))];
+        let err = run(&root).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Node X001: map \"upper($X001$)\" is not supported on a node with children, whose rows are keyed by its original values"
+        );
+
+        root.children[0].transformations = vec![Transformation::Aggregate(AggregationType::Count)];
+        let err = run(&root).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Node X001: aggregate \"count\" is not supported on a node with children, whose rows are keyed by its original values"
+        );
     }
-    Ok(())
 }
@@ -0,0 +1,145 @@
+//! # Database datasources
+//!
+//! This module contains the logic for running the queries of edges against database datasources.
+//! Each supported database system is handled by its own driver module.
+
+mod mysql;
+mod postgres;
+mod sqlite;
+
+use std::{collections::HashMap, str::FromStr, sync::Mutex};
+
+use anyhow::{anyhow, bail, Result};
+use strum::{Display, EnumString};
+
+use super::{Database, RecordStream};
+use crate::dvmql::datasources::DatasourceError;
+
+/// Database systems supported by database datasources.
+#[derive(Debug, Clone, Copy, PartialEq, EnumString, Display)]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+pub enum DatabaseSystem {
+    #[strum(serialize = "postgresql", serialize = "postgres")]
+    Postgresql,
+    Sqlite,
+    Mysql,
+}
+
+/// Connection pools of the database datasources, shared across the edges that use them.
+///
+/// SQLite datasources are plain files and open a connection per query instead.
+#[derive(Default)]
+pub struct ConnectionPools {
+    postgres: Mutex<HashMap<String, deadpool_postgres::Pool>>,
+    mysql: Mutex<HashMap<String, mysql_async::Pool>>,
+}
+
+impl ConnectionPools {
+    /// Returns the PostgreSQL pool of the datasource, creating it on first use.
+    fn postgres(&self, db: &Database) -> Result<deadpool_postgres::Pool> {
+        get_or_create_pool(&self.postgres, db, postgres::create_pool)
+    }
+
+    /// Returns the MySQL pool of the datasource, creating it on first use.
+    fn mysql(&self, db: &Database) -> Result<mysql_async::Pool> {
+        get_or_create_pool(&self.mysql, db, mysql::create_pool)
+    }
+}
+
+fn get_or_create_pool<P: Clone>(
+    pools: &Mutex<HashMap<String, P>>,
+    db: &Database,
+    create_pool: fn(&Database) -> Result<P>,
+) -> Result<P> {
+    let mut pools = pools
+        .lock()
+        .map_err(|_| anyhow!("Connection pools lock is poisoned"))?;
+    if let Some(pool) = pools.get(&db.name) {
+        return Ok(pool.clone());
+    }
+    let pool = create_pool(db)?;
+    pools.insert(db.name.clone(), pool.clone());
+    Ok(pool)
+}
+
+impl Database {
+    /// Returns the database system of the datasource.
+    pub fn system(&self) -> Result<DatabaseSystem, DatasourceError> {
+        DatabaseSystem::from_str(&self.system).map_err(|_| DatasourceError::UnsupportedSystem {
+            name: self.name.clone(),
+            system: self.system.clone(),
+        })
+    }
+
+    /// Runs the query of an edge and streams the (key, value) pairs at the given positions.
+    pub(super) async fn read_async(
+        &self,
+        query: &str,
+        key_pos: usize,
+        value_pos: usize,
+        pools: &ConnectionPools,
+    ) -> Result<RecordStream> {
+        let query = query.to_owned();
+        match self.system()? {
+            DatabaseSystem::Postgresql => {
+                postgres::read_async(pools.postgres(self)?, query, key_pos, value_pos).await
+            }
+            DatabaseSystem::Mysql => {
+                mysql::read_async(pools.mysql(self)?, query, key_pos, value_pos).await
+            }
+            DatabaseSystem::Sqlite => sqlite::read_async(self, query, key_pos, value_pos).await,
+        }
+    }
+}
+
+/// Helper function for splitting a `host[:port]` connection string.
+fn split_connection(connection: &str) -> Result<(&str, Option<u16>)> {
+    match connection.rsplit_once(':') {
+        Some((host, port)) => match port.parse() {
+            Ok(port) => Ok((host, Some(port))),
+            Err(_) => bail!("Invalid port in connection {}", connection),
+        },
+        None => Ok((connection, None)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_connection() {
+        assert_eq!(
+            split_connection("localhost:5432").unwrap(),
+            ("localhost", Some(5432))
+        );
+        assert_eq!(split_connection("localhost").unwrap(), ("localhost", None));
+        assert!(split_connection("localhost:port").is_err());
+    }
+
+    #[test]
+    fn test_database_system() {
+        let mut db = Database {
+            id: 1,
+            name: "db".to_string(),
+            system: "PostgreSQL".to_string(),
+            connection: "localhost".to_string(),
+            username: "user".to_string(),
+            password: "password".to_string(),
+            database: "db".to_string(),
+        };
+        assert_eq!(db.system().unwrap(), DatabaseSystem::Postgresql);
+        db.system = "postgres".to_string();
+        assert_eq!(db.system().unwrap(), DatabaseSystem::Postgresql);
+        db.system = "mysql".to_string();
+        assert_eq!(db.system().unwrap(), DatabaseSystem::Mysql);
+        db.system = "oracle".to_string();
+        assert_eq!(
+            db.system().unwrap_err(),
+            DatasourceError::UnsupportedSystem {
+                name: "db".to_string(),
+                system: "oracle".to_string(),
+            }
+        );
+    }
+}
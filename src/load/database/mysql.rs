@@ -0,0 +1,77 @@
+use anyhow::{anyhow, Result};
+use async_stream::stream;
+use mysql_async::{prelude::Queryable, OptsBuilder, Pool, Row, Value};
+use tracing::debug;
+
+use super::split_connection;
+use crate::load::{Database, RecordStream};
+
+/// Helper function for creating the connection pool of a MySQL datasource.
+pub(super) fn create_pool(db: &Database) -> Result<Pool> {
+    let (host, port) = split_connection(&db.connection)?;
+    let opts = OptsBuilder::default()
+        .ip_or_hostname(host)
+        .tcp_port(port.unwrap_or(3306))
+        .user(Some(&db.username))
+        .pass(Some(&db.password))
+        .db_name(Some(&db.database));
+    debug!("Creating MySQL connection pool for {}", db.name);
+    Ok(Pool::new(opts))
+}
+
+/// Runs the query through the text protocol and streams its rows.
+pub(super) async fn read_async(
+    pool: Pool,
+    query: String,
+    key_pos: usize,
+    value_pos: usize,
+) -> Result<RecordStream> {
+    let mut conn = pool
+        .get_conn()
+        .await
+        .map_err(|e| anyhow!("Failed to get a MySQL connection from the pool: {}", e))?;
+    let s = stream! {
+        let mut result = match conn.query_iter(query.as_str()).await {
+            Ok(result) => result,
+            Err(e) => {
+                yield Err(anyhow!("Failed to run query \"{}\": {}", query, e));
+                return;
+            }
+        };
+        loop {
+            match result.next().await {
+                Ok(Some(row)) => match (cell(&row, key_pos), cell(&row, value_pos)) {
+                    (Some(key), Some(value)) => yield Ok((key, value)),
+                    _ => {
+                        yield Err(anyhow!(
+                            "Query \"{}\" has no column at position {} or {}",
+                            query,
+                            key_pos + 1,
+                            value_pos + 1
+                        ));
+                        break;
+                    }
+                },
+                Ok(None) => break,
+                Err(e) => {
+                    yield Err(anyhow!("Failed to run query \"{}\": {}", query, e));
+                    break;
+                }
+            }
+        }
+    };
+    Ok(Box::pin(s))
+}
+
+/// Helper function for converting a cell of a row to its textual value.
+fn cell(row: &Row, pos: usize) -> Option<String> {
+    row.as_ref(pos).map(|value| match value {
+        Value::NULL => String::new(),
+        Value::Bytes(bytes) => String::from_utf8_lossy(bytes).into_owned(),
+        Value::Int(v) => v.to_string(),
+        Value::UInt(v) => v.to_string(),
+        Value::Float(v) => v.to_string(),
+        Value::Double(v) => v.to_string(),
+        value => value.as_sql(true).trim_matches('\'').to_owned(),
+    })
+}
@@ -0,0 +1,72 @@
+use anyhow::{anyhow, Context, Result};
+use async_stream::stream;
+use deadpool_postgres::{
+    tokio_postgres::{NoTls, SimpleQueryMessage},
+    Config, Pool, Runtime,
+};
+use futures_util::pin_mut;
+use tokio_stream::StreamExt;
+use tracing::debug;
+
+use super::split_connection;
+use crate::load::{Database, RecordStream};
+
+/// Helper function for creating the connection pool of a PostgreSQL datasource.
+pub(super) fn create_pool(db: &Database) -> Result<Pool> {
+    let (host, port) = split_connection(&db.connection)?;
+    let config = Config {
+        host: Some(host.to_owned()),
+        port,
+        user: Some(db.username.clone()),
+        password: Some(db.password.clone()),
+        dbname: Some(db.database.clone()),
+        ..Default::default()
+    };
+    debug!("Creating PostgreSQL connection pool for {}", db.name);
+    config
+        .create_pool(Some(Runtime::Tokio1), NoTls)
+        .with_context(|| format!("Failed to create connection pool for {}", db.name))
+}
+
+/// Runs the query through the simple query protocol, which returns every column as text.
+pub(super) async fn read_async(
+    pool: Pool,
+    query: String,
+    key_pos: usize,
+    value_pos: usize,
+) -> Result<RecordStream> {
+    let client = pool
+        .get()
+        .await
+        .context("Failed to get a PostgreSQL connection from the pool")?;
+    let s = stream! {
+        let rows = match client.simple_query_raw(&query).await {
+            Ok(rows) => rows,
+            Err(e) => {
+                yield Err(anyhow!("Failed to run query \"{}\": {}", query, e));
+                return;
+            }
+        };
+        pin_mut!(rows);
+        while let Some(message) = rows.next().await {
+            match message {
+                Ok(SimpleQueryMessage::Row(row)) => match (row.try_get(key_pos), row.try_get(value_pos)) {
+                    (Ok(key), Ok(value)) => yield Ok((
+                        key.unwrap_or_default().to_owned(),
+                        value.unwrap_or_default().to_owned(),
+                    )),
+                    (Err(e), _) | (_, Err(e)) => {
+                        yield Err(anyhow!("Failed to read row of query \"{}\": {}", query, e));
+                        break;
+                    }
+                },
+                Ok(_) => (),
+                Err(e) => {
+                    yield Err(anyhow!("Failed to run query \"{}\": {}", query, e));
+                    break;
+                }
+            }
+        }
+    };
+    Ok(Box::pin(s))
+}
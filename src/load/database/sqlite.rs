@@ -0,0 +1,98 @@
+use anyhow::{anyhow, Context, Result};
+use rusqlite::{types::ValueRef, Connection, OpenFlags};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::load::{Database, RecordStream};
+
+/// Number of rows buffered between the blocking SQLite reader and the stream.
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// Runs the query on a blocking thread, streaming its rows through a channel.
+///
+/// The `connection` of SQLite datasources is the path of the database file.
+pub(super) async fn read_async(
+    db: &Database,
+    query: String,
+    key_pos: usize,
+    value_pos: usize,
+) -> Result<RecordStream> {
+    let conn = Connection::open_with_flags(&db.connection, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("Failed to open SQLite database {}", db.connection))?;
+    let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+    tokio::task::spawn_blocking(move || {
+        let send_rows = || -> Result<()> {
+            let mut statement = conn.prepare(&query)?;
+            let mut rows = statement.query([])?;
+            while let Some(row) = rows.next()? {
+                let record = (cell(row.get_ref(key_pos)?), cell(row.get_ref(value_pos)?));
+                if tx.blocking_send(Ok(record)).is_err() {
+                    // The stream was dropped
+                    return Ok(());
+                }
+            }
+            Ok(())
+        };
+        if let Err(e) = send_rows() {
+            let _ = tx.blocking_send(Err(anyhow!("Failed to run query \"{}\": {}", query, e)));
+        }
+    });
+    Ok(Box::pin(ReceiverStream::new(rx)))
+}
+
+/// Helper function for converting a cell of a row to its textual value.
+fn cell(value: ValueRef) -> String {
+    match value {
+        ValueRef::Null => String::new(),
+        ValueRef::Integer(v) => v.to_string(),
+        ValueRef::Real(v) => v.to_string(),
+        ValueRef::Text(v) | ValueRef::Blob(v) => String::from_utf8_lossy(v).into_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_fs::TempDir;
+    use tokio_stream::StreamExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sqlite_read_async() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("test.sqlite");
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE people (id INTEGER, name TEXT, score REAL); \
+            INSERT INTO people VALUES (1, 'John', 1.5), (2, 'Paul', NULL);",
+        )
+        .unwrap();
+        let db = Database {
+            id: 1,
+            name: "test".to_string(),
+            system: "sqlite".to_string(),
+            connection: path.to_str().unwrap().to_string(),
+            username: String::new(),
+            password: String::new(),
+            database: String::new(),
+        };
+        let query = "SELECT id, name, score FROM people ORDER BY id".to_string();
+        let records: Vec<(String, String)> = read_async(&db, query, 0, 2)
+            .await
+            .unwrap()
+            .map(|record| record.unwrap())
+            .collect()
+            .await;
+        assert_eq!(
+            records,
+            vec![
+                ("1".to_string(), "1.5".to_string()),
+                ("2".to_string(), String::new()),
+            ]
+        );
+
+        let query = "SELECT id FROM missing".to_string();
+        let mut records = read_async(&db, query, 0, 1).await.unwrap();
+        assert!(records.next().await.unwrap().is_err());
+    }
+}
@@ -1,13 +1,26 @@
-use std::sync::Arc;
+use std::{
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+};
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use async_stream::stream;
+use calamine::{open_workbook_auto, Data, DataType, Reader};
 use quick_xml::events::Event;
 use tokio::{fs::File, sync::Mutex};
 use tokio_stream::{Stream, StreamExt};
 
+use self::edges::Edge;
+
+pub mod database;
 pub mod edges;
 
+pub use self::database::{ConnectionPools, DatabaseSystem};
+
+/// Stream of (key, value) records read from a datasource.
+pub type RecordStream = Pin<Box<dyn Stream<Item = Result<(String, String)>> + Send>>;
+
 /// Enum representing the different types of datasources.
 #[derive(Debug, PartialEq)]
 pub enum Datasource {
@@ -62,35 +75,102 @@ pub struct Excel {
 
 // TODO:
 //
-// - [ ] Clone reading xml from original data-mingler
-// - [ ] Add functionality for reading from postgres DB
 // - [ ] Integration testing for the `load` module: use dockertest crate
 // - [ ] Make all streams return a Result of a custom record type
 
+impl Datasource {
+    /// Opens the datasource and streams the (key, value) pairs described by the given edge.
+    ///
+    /// The key and value positions of DVM edges are 1-based. Database datasources run the
+    /// query of the edge through the shared connection pools.
+    pub async fn read_async(&self, edge: &Edge, pools: &ConnectionPools) -> Result<RecordStream> {
+        let key_pos = to_index(edge.key_pos, "key")?;
+        let value_pos = to_index(edge.value_pos, "value")?;
+        match self {
+            Datasource::Csv(csv) => Ok(Box::pin(csv.read_async(key_pos, value_pos).await?)),
+            Datasource::Xml(xml) => Ok(Box::pin(xml.read_rows_async(key_pos, value_pos).await?)),
+            Datasource::Excel(excel) => Ok(Box::pin(excel.read_async(key_pos, value_pos).await?)),
+            Datasource::Database(db) => {
+                let query = edge
+                    .query
+                    .as_deref()
+                    .filter(|query| !query.is_empty())
+                    .with_context(|| {
+                        format!("Edge on database datasource {} has no query", db.name)
+                    })?;
+                db.read_async(query, key_pos, value_pos, pools).await
+            }
+        }
+    }
+
+    /// Returns the file the datasource is read from, if any.
+    ///
+    /// The `connection` of SQLite datasources is the path of the database file.
+    pub fn file(&self) -> Option<PathBuf> {
+        match self {
+            Datasource::Csv(csv) => Some(file_path(&csv.path, &csv.filename)),
+            Datasource::Xml(xml) => Some(file_path(&xml.path, &xml.filename)),
+            Datasource::Excel(excel) => Some(file_path(&excel.path, &excel.filename)),
+            Datasource::Database(db) => match db.system() {
+                Ok(DatabaseSystem::Sqlite) => Some(PathBuf::from(&db.connection)),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// Helper function for converting a 1-based edge position to a 0-based index.
+fn to_index(pos: u32, field: &str) -> Result<usize> {
+    let pos = usize::try_from(pos)?;
+    pos.checked_sub(1)
+        .with_context(|| format!("Edge {} position must be 1-based, found 0", field))
+}
+
+/// Helper function for building the full path of a file-based datasource.
+fn file_path(path: &str, filename: &str) -> PathBuf {
+    Path::new(path).join(filename)
+}
+
 impl Csv {
     async fn read_async(
         &self,
         key_pos: usize,
         value_pos: usize,
     ) -> Result<impl Stream<Item = Result<(String, String)>>> {
-        let file = File::open(&self.path).await?;
+        let path = file_path(&self.path, &self.filename);
+        let file = File::open(&path)
+            .await
+            .with_context(|| format!("Failed to open file: {}", path.display()))?;
+        let delimiter = u8::try_from(self.delimiter)
+            .with_context(|| format!("CSV delimiter must be ASCII, found {}", self.delimiter))?;
         let reader = csv_async::AsyncReaderBuilder::new()
             .has_headers(self.has_headers)
+            .delimiter(delimiter)
             .create_reader(file);
         Ok(reader
             .into_records()
             .map(move |x| -> Result<(String, String)> {
                 let record = x?;
-                let key = record.get(key_pos).unwrap().to_owned();
-                let value = record.get(value_pos).unwrap().to_owned();
+                let key = record
+                    .get(key_pos)
+                    .with_context(|| format!("No key column at position {}", key_pos + 1))?
+                    .to_owned();
+                let value = record
+                    .get(value_pos)
+                    .with_context(|| format!("No value column at position {}", value_pos + 1))?
+                    .to_owned();
                 Ok((key, value))
             }))
     }
 }
 
 impl Xml {
-    async fn read_async(&self) -> Result<impl Stream<Item = Result<(String, String)>>> {
-        let file = File::open(&self.path).await?;
+    /// Streams the (element name, text) pairs of every leaf element in the file.
+    pub async fn read_async(&self) -> Result<impl Stream<Item = Result<(String, String)>>> {
+        let path = file_path(&self.path, &self.filename);
+        let file = File::open(&path)
+            .await
+            .with_context(|| format!("Failed to open file: {}", path.display()))?;
         let reader = tokio::io::BufReader::new(file);
         let mut reader = quick_xml::Reader::from_reader(reader);
         let buf = Arc::new(Mutex::new(Vec::new()));
@@ -106,11 +186,9 @@ impl Xml {
                     Ok(Event::Text(e)) =>
                         value = Some(e.unescape()?.into_owned()),
                     Ok(Event::End(_)) => {
-                        if key.is_some() && value.is_some() {
-                            yield Ok((key.unwrap(), value.unwrap()));
+                        if let (Some(k), Some(v)) = (key.take(), value.take()) {
+                            yield Ok((k, v));
                         }
-                        key = None;
-                        value = None;
                     }
                     Err(e) => {
                         yield Err(anyhow!("Error reading event: {:?}", e));
@@ -123,6 +201,141 @@ impl Xml {
         buf.lock().await.clear();
         Ok(s)
     }
+
+    /// Streams the (key, value) pairs of every row in the file.
+    ///
+    /// Each child of the root element is a row and the children of a row are its columns,
+    /// like in the original DataMingler.
+    async fn read_rows_async(
+        &self,
+        key_pos: usize,
+        value_pos: usize,
+    ) -> Result<impl Stream<Item = Result<(String, String)>>> {
+        let path = file_path(&self.path, &self.filename);
+        let file = File::open(&path)
+            .await
+            .with_context(|| format!("Failed to open file: {}", path.display()))?;
+        let reader = tokio::io::BufReader::new(file);
+        let mut reader = quick_xml::Reader::from_reader(reader);
+        let s = stream! {
+            let mut buf = Vec::new();
+            let mut depth = 0;
+            let mut columns: Vec<String> = Vec::new();
+            loop {
+                match reader.read_event_into_async(&mut buf).await {
+                    Ok(Event::Eof) => break,
+                    Ok(Event::Start(_)) => {
+                        depth += 1;
+                        match depth {
+                            2 => columns.clear(),
+                            3 => columns.push(String::new()),
+                            _ => (),
+                        }
+                    }
+                    Ok(Event::Empty(_)) if depth == 2 => columns.push(String::new()),
+                    Ok(Event::Text(e)) if depth == 3 => match e.unescape() {
+                        Ok(text) => {
+                            if let Some(column) = columns.last_mut() {
+                                column.push_str(text.trim());
+                            }
+                        }
+                        Err(e) => {
+                            yield Err(anyhow!("Error reading text: {:?}", e));
+                            break;
+                        }
+                    },
+                    Ok(Event::End(_)) => {
+                        if depth == 2 {
+                            match (columns.get(key_pos), columns.get(value_pos)) {
+                                (Some(k), Some(v)) => yield Ok((k.clone(), v.clone())),
+                                _ => {
+                                    yield Err(anyhow!(
+                                        "Row with {} columns has no column at position {} or {}",
+                                        columns.len(),
+                                        key_pos + 1,
+                                        value_pos + 1
+                                    ));
+                                    break;
+                                }
+                            }
+                        }
+                        depth -= 1;
+                    }
+                    Err(e) => {
+                        yield Err(anyhow!("Error reading event: {:?}", e));
+                        break;
+                    }
+                    Ok(_) => (),
+                };
+                buf.clear();
+            }
+        };
+        Ok(s)
+    }
+}
+
+impl Excel {
+    async fn read_async(
+        &self,
+        key_pos: usize,
+        value_pos: usize,
+    ) -> Result<impl Stream<Item = Result<(String, String)>>> {
+        let path = file_path(&self.path, &self.filename);
+        let sheet = self.sheet.clone();
+        let range = {
+            let (path, sheet) = (path.clone(), sheet.clone());
+            tokio::task::spawn_blocking(move || -> Result<calamine::Range<Data>> {
+                let mut workbook = open_workbook_auto(&path)
+                    .with_context(|| format!("Failed to open file: {}", path.display()))?;
+                workbook.worksheet_range(&sheet).with_context(|| {
+                    format!("Failed to read sheet {} of {}", sheet, path.display())
+                })
+            })
+            .await??
+        };
+        // Positions are relative to the first column of the sheet, not of the used range
+        let (start_row, start_col) = range.start().unwrap_or_default();
+        let (start_row, start_col) = (usize::try_from(start_row)?, usize::try_from(start_col)?);
+        let skip = usize::from(self.has_headers);
+        let s = stream! {
+            for (i, row) in range.rows().enumerate().skip(skip) {
+                // Cells past the used range are missing, while empty cells within it are kept
+                let cell = |pos: usize, field: &str| {
+                    pos.checked_sub(start_col)
+                        .and_then(|pos| row.get(pos))
+                        .map(cell_to_string)
+                        .with_context(|| {
+                            format!(
+                                "Row {} of sheet {} in {} has no {} column at position {}",
+                                start_row + i + 1,
+                                sheet,
+                                path.display(),
+                                field,
+                                pos + 1
+                            )
+                        })
+                };
+                match (cell(key_pos, "key"), cell(value_pos, "value")) {
+                    (Ok(key), Ok(value)) => yield Ok((key, value)),
+                    (Err(e), _) | (_, Err(e)) => {
+                        yield Err(e);
+                        break;
+                    }
+                }
+            }
+        };
+        Ok(s)
+    }
+}
+
+/// Helper function for converting a spreadsheet cell to its textual value.
+fn cell_to_string(cell: &Data) -> String {
+    match cell {
+        Data::DateTime(_) | Data::DateTimeIso(_) => cell
+            .as_datetime()
+            .map_or_else(|| cell.to_string(), |dt| dt.to_string()),
+        _ => cell.to_string(),
+    }
 }
 
 #[cfg(test)]
@@ -131,20 +344,19 @@ mod tests {
     use futures_util::pin_mut;
     use std::path::PathBuf;
 
-    fn get_test_data_path(file: &str) -> String {
+    fn get_test_data_path() -> String {
         let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-        let path_str = "test_data/".to_string() + file;
-        path.push(path_str);
+        path.push("test_data/");
         String::from(path.to_str().unwrap())
     }
 
     #[tokio::test]
     async fn test_csv_read_async_ignoring_headers() {
-        let path = get_test_data_path("example_csv.csv");
+        let path = get_test_data_path();
         let csv = Csv {
             id: 1,
             name: "test".to_string(),
-            filename: "test.csv".to_string(),
+            filename: "example_csv.csv".to_string(),
             path,
             delimiter: ',',
             has_headers: true,
@@ -165,11 +377,11 @@ mod tests {
 
     #[tokio::test]
     async fn test_csv_read_async_with_headers() {
-        let path = get_test_data_path("example_csv.csv");
+        let path = get_test_data_path();
         let csv = Csv {
             id: 1,
             name: "test".to_string(),
-            filename: "test.csv".to_string(),
+            filename: "example_csv.csv".to_string(),
             path,
             delimiter: ',',
             has_headers: false,
@@ -190,11 +402,11 @@ mod tests {
 
     #[tokio::test]
     async fn test_xml_read_async() {
-        let path = get_test_data_path("example_xml.xml");
+        let path = get_test_data_path();
         let xml = Xml {
             id: 1,
             name: "test".to_string(),
-            filename: "test.xml".to_string(),
+            filename: "example_xml.xml".to_string(),
             path,
         };
         let keys = ["int_id", "name"];
@@ -209,4 +421,165 @@ mod tests {
         }
         assert_eq!(count, 8);
     }
+
+    #[tokio::test]
+    async fn test_datasource_read_async_xml_rows() {
+        let datasource = Datasource::Xml(Xml {
+            id: 1,
+            name: "test".to_string(),
+            filename: "example_xml.xml".to_string(),
+            path: get_test_data_path(),
+        });
+        let edge = Edge {
+            datasource_name: "test".to_string(),
+            key_pos: 1,
+            value_pos: 2,
+            query: None,
+        };
+        let records: Vec<(String, String)> = datasource
+            .read_async(&edge, &ConnectionPools::default())
+            .await
+            .unwrap()
+            .map(|record| record.unwrap())
+            .collect()
+            .await;
+        assert_eq!(
+            records,
+            vec![
+                ("0".to_string(), "John".to_string()),
+                ("1".to_string(), "Paul".to_string()),
+                ("2".to_string(), "George".to_string()),
+                ("3".to_string(), "Ringo".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_datasource_read_async_rejects_zero_position() {
+        let datasource = Datasource::Csv(Csv {
+            id: 1,
+            name: "test".to_string(),
+            filename: "example_csv.csv".to_string(),
+            path: get_test_data_path(),
+            delimiter: ',',
+            has_headers: true,
+        });
+        let edge = Edge {
+            datasource_name: "test".to_string(),
+            key_pos: 0,
+            value_pos: 2,
+            query: None,
+        };
+        assert!(datasource
+            .read_async(&edge, &ConnectionPools::default())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_excel_read_async() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let mut workbook = rust_xlsxwriter::Workbook::new();
+        let worksheet = workbook.add_worksheet();
+        worksheet.set_name("Sheet1").unwrap();
+        worksheet.write_row(0, 0, ["id", "name"]).unwrap();
+        worksheet.write_number(1, 0, 1).unwrap();
+        worksheet.write_string(1, 1, "John").unwrap();
+        worksheet.write_number(2, 0, 2).unwrap();
+        worksheet.write_string(2, 1, "Paul").unwrap();
+        workbook.save(dir.path().join("test.xlsx")).unwrap();
+
+        let datasource = Datasource::Excel(Excel {
+            id: 1,
+            name: "test".to_string(),
+            filename: "test.xlsx".to_string(),
+            path: dir.path().to_str().unwrap().to_string(),
+            sheet: "Sheet1".to_string(),
+            has_headers: true,
+        });
+        let edge = Edge {
+            datasource_name: "test".to_string(),
+            key_pos: 1,
+            value_pos: 2,
+            query: None,
+        };
+        let records: Vec<(String, String)> = datasource
+            .read_async(&edge, &ConnectionPools::default())
+            .await
+            .unwrap()
+            .map(|record| record.unwrap())
+            .collect()
+            .await;
+        assert_eq!(
+            records,
+            vec![
+                ("1".to_string(), "John".to_string()),
+                ("2".to_string(), "Paul".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_excel_read_async_rejects_position_out_of_range() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let mut workbook = rust_xlsxwriter::Workbook::new();
+        let worksheet = workbook.add_worksheet();
+        worksheet.set_name("Sheet1").unwrap();
+        worksheet.write_row(0, 1, ["id", "name", "email"]).unwrap();
+        worksheet.write_number(1, 1, 1).unwrap();
+        worksheet.write_string(1, 3, "john@example.com").unwrap();
+        workbook.save(dir.path().join("test.xlsx")).unwrap();
+
+        let read = |key_pos: u32, value_pos: u32| {
+            let datasource = Datasource::Excel(Excel {
+                id: 1,
+                name: "test".to_string(),
+                filename: "test.xlsx".to_string(),
+                path: dir.path().to_str().unwrap().to_string(),
+                sheet: "Sheet1".to_string(),
+                has_headers: true,
+            });
+            let edge = Edge {
+                datasource_name: "test".to_string(),
+                key_pos,
+                value_pos,
+                query: None,
+            };
+            async move {
+                datasource
+                    .read_async(&edge, &ConnectionPools::default())
+                    .await
+                    .unwrap()
+                    .collect::<Vec<_>>()
+                    .await
+            }
+        };
+
+        // An empty cell within the used range is read as an empty value
+        let records = read(2, 3).await;
+        assert_eq!(records.len(), 1);
+        assert_eq!(
+            records[0].as_ref().unwrap(),
+            &("1".to_string(), "".to_string())
+        );
+
+        let path = dir.path().join("test.xlsx");
+        let records = read(2, 5).await;
+        assert_eq!(records.len(), 1);
+        assert_eq!(
+            records[0].as_ref().unwrap_err().to_string(),
+            format!(
+                "Row 2 of sheet Sheet1 in {} has no value column at position 5",
+                path.display()
+            )
+        );
+        let records = read(1, 2).await;
+        assert_eq!(
+            records[0].as_ref().unwrap_err().to_string(),
+            format!(
+                "Row 2 of sheet Sheet1 in {} has no key column at position 1",
+                path.display()
+            )
+        );
+    }
 }
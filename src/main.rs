@@ -1,32 +1,182 @@
-use anyhow::Result;
-use clap::Parser;
-use neo4rs::{query, Graph};
-use tracing::Level;
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{bail, Context, Result};
+use clap::{
+    builder::{PossibleValue, PossibleValuesParser, TypedValueParser},
+    Parser, Subcommand,
+};
+use tracing::{info, Level};
 use tracing_subscriber::FmtSubscriber;
 
-use data_mingler_rust::{dfs, dvmql::datasources, dvmql::query::load_query_xml};
+use data_mingler_rust::{
+    dfs,
+    dvmql::datasources,
+    dvmql::query::{load_query_xml, tree::TreeNode},
+    explain::{explain, ExplainFormat},
+    join::{JoinMode, DEFAULT_MAX_THETA_COMBINATIONS},
+    load::{ConnectionPools, Datasource},
+    neo4j::Neo4jArgs,
+    output::{csv::write_csv, excel::write_excel, OutputFormat},
+    store::{DvmStore, MemoryStore, Neo4jStore, SqliteStore},
+    validate::validate,
+};
 
-// TODO: Add arguments for neo4j db
 #[derive(Parser, Debug)]
-#[command(author, version, about)]
+#[command(
+    author,
+    version,
+    about,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Args {
-    datasources_path: String,
-    query_path: String,
-    #[arg(short, long, default_value_t = String::from("NONE"))]
-    output: String,
-    #[arg(short, long, default_value_t = String::from("ALL"))]
-    mode: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    query: QueryArgs,
+    #[arg(short, long, default_value = "NONE")]
+    output: OutputFormat,
+    #[arg(long)]
+    output_path: Option<String>,
+    /// Split EXCEL results larger than the sheet row limit across multiple sheets
+    #[arg(long)]
+    split_sheets: bool,
+    #[arg(short, long, default_value = "ALL")]
+    mode: JoinMode,
+    /// Fail the query when a theta has more combinations of values than this to evaluate for a key
+    #[arg(long, default_value_t = DEFAULT_MAX_THETA_COMBINATIONS)]
+    max_theta_combinations: usize,
+}
+
+/// Arguments locating the query, its datasources and the DVM.
+#[derive(clap::Args, Debug)]
+struct QueryArgs {
+    #[arg(required = true)]
+    datasources_path: Option<String>,
+    #[arg(required = true)]
+    query_path: Option<String>,
+    /// Read the DVM from this DVM XML file instead of Neo4j
+    #[arg(long)]
+    dvm: Option<String>,
+    /// Read the DVM from this SQLite file, as loaded by dvm-to-neo4j, instead of Neo4j
+    #[arg(long, conflicts_with = "dvm")]
+    dvm_sqlite: Option<String>,
+    #[command(flatten)]
+    neo4j: Neo4jArgs,
     #[arg(short, long, action = clap::ArgAction::Count)]
     debug: u8,
 }
 
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Checks the query against the datasources and the DVM, without executing it
+    Validate(QueryArgs),
+    /// Prints the execution plan of the query, with every edge resolved from the DVM
+    Explain(ExplainArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct ExplainArgs {
+    #[command(flatten)]
+    query: QueryArgs,
+    /// Format the plan is printed in
+    #[arg(short, long, default_value = "TEXT", ignore_case = true, value_parser = explain_format_parser())]
+    format: ExplainFormat,
+}
+
+/// Parser of the explain format, listing its possible values in the help.
+fn explain_format_parser() -> impl TypedValueParser<Value = ExplainFormat> {
+    PossibleValuesParser::new([
+        PossibleValue::new("TEXT")
+            .help("Indented tree of the nodes, with their edges and transformations"),
+        PossibleValue::new("JSON").help("JSON document of the plan nodes"),
+    ])
+    .try_map(|format| format.parse::<ExplainFormat>())
+}
+
+impl QueryArgs {
+    /// Loads the query tree and the datasources.
+    fn load(&self) -> Result<(TreeNode, HashMap<String, Datasource>)> {
+        let (query_path, datasources_path) = self.paths()?;
+        let tree = load_query_xml(query_path)?;
+        let datasources = datasources::load_datasources_xml(datasources_path)?;
+        Ok((tree, datasources))
+    }
+
+    /// Checks the query, reporting the errors of loading the query and the datasources as
+    /// problems and checking whatever loaded.
+    async fn validate(&self) -> Result<Vec<String>> {
+        let (query_path, datasources_path) = self.paths()?;
+        let mut problems = vec![];
+        let tree = match load_query_xml(query_path) {
+            Ok(tree) => Some(tree),
+            Err(e) => {
+                problems.push(format!("{:#}", e));
+                None
+            }
+        };
+        let (datasources, invalid_datasources) =
+            match datasources::load_valid_datasources_xml(datasources_path) {
+                Ok((datasources, errors)) => {
+                    problems.extend(errors.iter().map(|e| e.to_string()));
+                    let invalid = errors.iter().map(|e| e.name().to_string()).collect();
+                    (datasources, invalid)
+                }
+                Err(e) => {
+                    problems.push(format!("{:#}", e));
+                    (HashMap::new(), HashSet::new())
+                }
+            };
+        let store = match self.store().await {
+            Ok(store) => Some(store),
+            Err(e) => {
+                problems.push(format!("{:#}", e));
+                None
+            }
+        };
+        if let Some(tree) = tree {
+            problems.extend(
+                validate(&tree, &datasources, &invalid_datasources, store.as_deref()).await?,
+            );
+        }
+        Ok(problems)
+    }
+
+    /// Helper function for getting the query and datasources paths.
+    fn paths(&self) -> Result<(&str, &str)> {
+        let query_path = self
+            .query_path
+            .as_deref()
+            .context("A query path is required")?;
+        let datasources_path = self
+            .datasources_path
+            .as_deref()
+            .context("A datasources path is required")?;
+        Ok((query_path, datasources_path))
+    }
+
+    /// Opens the store the DVM is read from.
+    async fn store(&self) -> Result<Box<dyn DvmStore>> {
+        Ok(match (&self.dvm, &self.dvm_sqlite) {
+            (Some(dvm_path), _) => Box::new(MemoryStore::load(dvm_path)?),
+            (None, Some(sqlite_path)) => Box::new(SqliteStore::open_read_only(sqlite_path)?),
+            (None, None) => Box::new(Neo4jStore::new(self.neo4j.resolve()?.connect().await?)),
+        })
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // Parse CLI arguments
     let args = Args::parse();
+    let query_args = match &args.command {
+        Some(Command::Validate(query_args)) => query_args,
+        Some(Command::Explain(explain_args)) => &explain_args.query,
+        None => &args.query,
+    };
 
     // Initialize logger
-    let log_level = match args.debug {
+    let log_level = match query_args.debug {
         1 => Level::INFO,
         2 => Level::DEBUG,
         3 => Level::TRACE,
@@ -35,16 +185,58 @@ async fn main() -> Result<()> {
     let subscriber = FmtSubscriber::builder().with_max_level(log_level).finish();
     tracing::subscriber::set_global_default(subscriber).expect("Setting default subscriber failed");
 
-    // Initialize Neo4j graph
-    let neo4j = Graph::new("bolt://localhost:7687", "neo4j", "12345678").await?;
-    assert!(neo4j.run(query("RETURN 1")).await.is_ok());
+    if let Some(Command::Validate(_)) = args.command {
+        let problems = query_args.validate().await?;
+        for problem in &problems {
+            println!("{}", problem);
+        }
+        if !problems.is_empty() {
+            bail!("Query is invalid: found {} problem(s)", problems.len());
+        }
+        println!("Query is valid");
+        return Ok(());
+    }
 
     // Load query & datasources
-    let tree = load_query_xml(&args.query_path)?;
-    let datasources = datasources::load_datasources_xml(&args.datasources_path)?;
+    let (tree, datasources) = query_args.load()?;
+
+    // Initialize DVM store
+    let store = query_args.store().await?;
+
+    if let Some(Command::Explain(explain_args)) = &args.command {
+        let plan = explain(&tree, &datasources, store.as_ref()).await?;
+        match explain_args.format {
+            ExplainFormat::Text => print!("{}", plan),
+            ExplainFormat::Json => println!("{}", serde_json::to_string_pretty(&plan)?),
+        }
+        return Ok(());
+    }
 
     // Execute query
-    dfs(&tree, &neo4j, &datasources).await?;
+    let pools = ConnectionPools::default();
+    let table = dfs(
+        &tree,
+        store.as_ref(),
+        &datasources,
+        &pools,
+        args.mode,
+        args.max_theta_combinations,
+    )
+    .await?;
+    info!("Query produced {} rows", table.rows.len());
+
+    // Write output
+    match args.output {
+        OutputFormat::None => (),
+        OutputFormat::Csv => {
+            let path = args.output_path.as_deref().unwrap_or("output.csv");
+            write_csv(path, &tree, &table).await?;
+        }
+        OutputFormat::Excel => {
+            let path = args.output_path.as_deref().unwrap_or("output.xlsx");
+            write_excel(path, &tree, &table, args.split_sheets)?;
+        }
+    }
 
     Ok(())
 }
@@ -0,0 +1,368 @@
+//! # Neo4j
+//!
+//! This module contains the Neo4j connection settings and connection helper shared by the
+//! binaries.
+//!
+//! Each setting is taken from the first source defining it: command line flag, environment
+//! variable, config file, then the default of the local docker-compose setup.
+
+use std::{path::Path, time::Duration};
+
+use anyhow::{Context, Result};
+use clap::Args;
+use neo4rs::{query, ConfigBuilder, Graph};
+use serde::Deserialize;
+use thiserror::Error;
+use tracing::{debug, warn};
+
+/// Config file read when `--config` is not given, if it exists.
+const DEFAULT_CONFIG_PATH: &str = "data-mingler.toml";
+
+const DEFAULT_URI: &str = "bolt://localhost:7687";
+const DEFAULT_USER: &str = "neo4j";
+const DEFAULT_PASSWORD: &str = "12345678";
+const DEFAULT_CONNECT_RETRIES: u32 = 5;
+const DEFAULT_CONNECT_BACKOFF_MS: u64 = 500;
+const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 10;
+
+/// Upper bound of the delay between two connection attempts.
+const MAX_CONNECT_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Command line arguments for connecting to Neo4j.
+#[derive(Args, Debug, Default)]
+pub struct Neo4jArgs {
+    /// Bolt URI of the Neo4j server [default: bolt://localhost:7687]
+    #[arg(long, env = "NEO4J_URI")]
+    pub bolt_uri: Option<String>,
+    /// Neo4j user [default: neo4j]
+    #[arg(long, env = "NEO4J_USER")]
+    pub neo4j_user: Option<String>,
+    /// Neo4j password [default: 12345678]
+    #[arg(long, env = "NEO4J_PASSWORD", hide_env_values = true)]
+    pub neo4j_password: Option<String>,
+    /// Neo4j database [default: the server's default database]
+    #[arg(long, env = "NEO4J_DATABASE")]
+    pub neo4j_database: Option<String>,
+    /// Connection attempts retried while Neo4j is unreachable [default: 5]
+    #[arg(long, env = "NEO4J_CONNECT_RETRIES")]
+    pub connect_retries: Option<u32>,
+    /// Delay before the first retry, doubled after each attempt [default: 500]
+    #[arg(long, env = "NEO4J_CONNECT_BACKOFF_MS")]
+    pub connect_backoff_ms: Option<u64>,
+    /// Timeout of each connection attempt [default: 10]
+    #[arg(long, env = "NEO4J_CONNECT_TIMEOUT_SECS")]
+    pub connect_timeout_secs: Option<u64>,
+    /// TOML config file with a [neo4j] section [default: data-mingler.toml, if present]
+    #[arg(long, env = "DATA_MINGLER_CONFIG")]
+    pub config: Option<String>,
+}
+
+/// Config file holding the Neo4j connection settings.
+#[derive(Deserialize, Debug, Default, PartialEq)]
+struct ConfigFile {
+    #[serde(default)]
+    neo4j: Neo4jSection,
+}
+
+/// `[neo4j]` section of the config file.
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct Neo4jSection {
+    uri: Option<String>,
+    user: Option<String>,
+    password: Option<String>,
+    database: Option<String>,
+    connect_retries: Option<u32>,
+    connect_backoff_ms: Option<u64>,
+    connect_timeout_secs: Option<u64>,
+}
+
+/// Resolved Neo4j connection settings.
+#[derive(Debug, PartialEq)]
+pub struct Neo4jConfig {
+    pub uri: String,
+    pub user: String,
+    pub password: String,
+    pub database: Option<String>,
+    pub connect_retries: u32,
+    pub connect_backoff: Duration,
+    pub connect_timeout: Duration,
+}
+
+/// Error returned when connecting to Neo4j fails.
+#[derive(Debug, Error)]
+pub enum ConnectionError {
+    #[error("Neo4j at {uri} is unreachable after {attempts} attempt(s): {reason}. Check that the server is running and that --bolt-uri points to it.")]
+    Unreachable {
+        uri: String,
+        attempts: u32,
+        reason: String,
+    },
+    #[error("Neo4j at {uri} rejected the credentials of user {user}: {reason}. Check --neo4j-user and --neo4j-password.")]
+    Authentication {
+        uri: String,
+        user: String,
+        reason: String,
+    },
+    #[error(
+        "Database {database} does not exist on Neo4j at {uri}: {reason}. Check --neo4j-database."
+    )]
+    DatabaseNotFound {
+        uri: String,
+        database: String,
+        reason: String,
+    },
+    #[error("Failed to connect to Neo4j at {uri}: {reason}")]
+    Other { uri: String, reason: String },
+}
+
+impl Neo4jArgs {
+    /// Resolves the connection settings, falling back to the config file and the defaults.
+    pub fn resolve(&self) -> Result<Neo4jConfig> {
+        let file = match &self.config {
+            Some(path) => read_config_file(path)?,
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                read_config_file(DEFAULT_CONFIG_PATH)?
+            }
+            None => ConfigFile::default(),
+        };
+        Ok(self.merge(file.neo4j))
+    }
+
+    fn merge(&self, section: Neo4jSection) -> Neo4jConfig {
+        Neo4jConfig {
+            uri: self
+                .bolt_uri
+                .clone()
+                .or(section.uri)
+                .unwrap_or_else(|| DEFAULT_URI.to_owned()),
+            user: self
+                .neo4j_user
+                .clone()
+                .or(section.user)
+                .unwrap_or_else(|| DEFAULT_USER.to_owned()),
+            password: self
+                .neo4j_password
+                .clone()
+                .or(section.password)
+                .unwrap_or_else(|| DEFAULT_PASSWORD.to_owned()),
+            database: self.neo4j_database.clone().or(section.database),
+            connect_retries: self
+                .connect_retries
+                .or(section.connect_retries)
+                .unwrap_or(DEFAULT_CONNECT_RETRIES),
+            connect_backoff: Duration::from_millis(
+                self.connect_backoff_ms
+                    .or(section.connect_backoff_ms)
+                    .unwrap_or(DEFAULT_CONNECT_BACKOFF_MS),
+            ),
+            connect_timeout: Duration::from_secs(
+                self.connect_timeout_secs
+                    .or(section.connect_timeout_secs)
+                    .unwrap_or(DEFAULT_CONNECT_TIMEOUT_SECS),
+            ),
+        }
+    }
+}
+
+/// Helper function for reading and deserializing the TOML config file.
+fn read_config_file(path: &str) -> Result<ConfigFile> {
+    debug!("Reading config file {}", path);
+    let content =
+        std::fs::read_to_string(path).with_context(|| format!("Failed to open file: {}", path))?;
+    toml::from_str(&content).with_context(|| format!("Failed to parse config file: {}", path))
+}
+
+impl Neo4jConfig {
+    /// Connects to the Neo4j server and checks that it answers queries.
+    ///
+    /// Attempts that fail because the server is unreachable or time out are retried with
+    /// exponential backoff, while authentication and missing database errors fail right away.
+    pub async fn connect(&self) -> Result<Graph, ConnectionError> {
+        let mut backoff = self.connect_backoff;
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let error = match tokio::time::timeout(self.connect_timeout, self.try_connect()).await {
+                Ok(Ok(graph)) => {
+                    debug!("Connected to Neo4j at {}", self.uri);
+                    return Ok(graph);
+                }
+                Ok(Err(e)) => self.classify(e, attempts),
+                Err(_) => ConnectionError::Unreachable {
+                    uri: self.uri.clone(),
+                    attempts,
+                    reason: format!("timed out after {:?}", self.connect_timeout),
+                },
+            };
+            if !matches!(error, ConnectionError::Unreachable { .. })
+                || attempts > self.connect_retries
+            {
+                return Err(error);
+            }
+            warn!(
+                "Attempt {} to connect to Neo4j at {} failed, retrying in {:?}: {}",
+                attempts, self.uri, backoff, error
+            );
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_CONNECT_BACKOFF);
+        }
+    }
+
+    async fn try_connect(&self) -> Result<Graph, neo4rs::Error> {
+        let mut builder = ConfigBuilder::default()
+            .uri(&self.uri)
+            .user(&self.user)
+            .password(&self.password);
+        if let Some(database) = &self.database {
+            builder = builder.db(database.as_str());
+        }
+        let graph = Graph::connect(builder.build()?).await?;
+        graph.run(query("RETURN 1")).await?;
+        Ok(graph)
+    }
+
+    /// Helper function for telling apart the causes of a failed connection attempt.
+    fn classify(&self, error: neo4rs::Error, attempts: u32) -> ConnectionError {
+        let reason = error.to_string();
+        let uri = self.uri.clone();
+        match error {
+            neo4rs::Error::AuthenticationError(_) => ConnectionError::Authentication {
+                uri,
+                user: self.user.clone(),
+                reason,
+            },
+            _ if reason.contains("Security.Unauthorized") => ConnectionError::Authentication {
+                uri,
+                user: self.user.clone(),
+                reason,
+            },
+            _ if reason.contains("DatabaseNotFound") => ConnectionError::DatabaseNotFound {
+                uri,
+                database: self.database.clone().unwrap_or_default(),
+                reason,
+            },
+            neo4rs::Error::IOError { .. } | neo4rs::Error::ConnectionError => {
+                ConnectionError::Unreachable {
+                    uri,
+                    attempts,
+                    reason,
+                }
+            }
+            _ => ConnectionError::Other { uri, reason },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_defaults() {
+        let config = Neo4jArgs::default().merge(Neo4jSection::default());
+        assert_eq!(
+            config,
+            Neo4jConfig {
+                uri: DEFAULT_URI.to_string(),
+                user: DEFAULT_USER.to_string(),
+                password: DEFAULT_PASSWORD.to_string(),
+                database: None,
+                connect_retries: DEFAULT_CONNECT_RETRIES,
+                connect_backoff: Duration::from_millis(DEFAULT_CONNECT_BACKOFF_MS),
+                connect_timeout: Duration::from_secs(DEFAULT_CONNECT_TIMEOUT_SECS),
+            }
+        );
+    }
+
+    #[test]
+    fn test_resolve_prefers_args_over_config_file() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(
+            &path,
+            "[neo4j]\nuri = \"bolt://staging:7687\"\nuser = \"staging\"\ndatabase = \"dvm\"\nconnect_retries = 10\n",
+        )
+        .unwrap();
+        let args = Neo4jArgs {
+            neo4j_user: Some("admin".to_string()),
+            config: Some(path.to_str().unwrap().to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            args.resolve().unwrap(),
+            Neo4jConfig {
+                uri: "bolt://staging:7687".to_string(),
+                user: "admin".to_string(),
+                password: DEFAULT_PASSWORD.to_string(),
+                database: Some("dvm".to_string()),
+                connect_retries: 10,
+                connect_backoff: Duration::from_millis(DEFAULT_CONNECT_BACKOFF_MS),
+                connect_timeout: Duration::from_secs(DEFAULT_CONNECT_TIMEOUT_SECS),
+            }
+        );
+    }
+
+    #[test]
+    fn test_resolve_rejects_unknown_settings() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "[neo4j]\nhost = \"staging\"\n").unwrap();
+        let args = Neo4jArgs {
+            config: Some(path.to_str().unwrap().to_string()),
+            ..Default::default()
+        };
+        assert!(args.resolve().is_err());
+    }
+
+    fn get_config(uri: &str) -> Neo4jConfig {
+        Neo4jArgs {
+            bolt_uri: Some(uri.to_string()),
+            connect_retries: Some(2),
+            connect_backoff_ms: Some(1),
+            connect_timeout_secs: Some(5),
+            ..Default::default()
+        }
+        .merge(Neo4jSection::default())
+    }
+
+    #[tokio::test]
+    async fn test_connect_reports_unreachable_server() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+        let result = get_config(&format!("bolt://127.0.0.1:{}", port))
+            .connect()
+            .await;
+        assert!(matches!(
+            result,
+            Err(ConnectionError::Unreachable { attempts: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn test_classify() {
+        let config = get_config("bolt://localhost:7687");
+        assert!(matches!(
+            config.classify(neo4rs::Error::AuthenticationError("denied".to_string()), 1),
+            ConnectionError::Authentication { .. }
+        ));
+        assert!(matches!(
+            config.classify(
+                neo4rs::Error::UnexpectedMessage(
+                    "Neo.ClientError.Database.DatabaseNotFound".to_string()
+                ),
+                1
+            ),
+            ConnectionError::DatabaseNotFound { .. }
+        ));
+        assert!(matches!(
+            config.classify(neo4rs::Error::ConnectionError, 1),
+            ConnectionError::Unreachable { .. }
+        ));
+        assert!(matches!(
+            config.classify(neo4rs::Error::InvalidConfig, 1),
+            ConnectionError::Other { .. }
+        ));
+    }
+}
@@ -0,0 +1,101 @@
+//! # CSV output
+//!
+//! This module contains the writer for the CSV output format.
+
+use anyhow::{Context, Result};
+use tokio::fs::File;
+use tracing::{debug, info};
+
+use super::{format_cell, output_columns, select_cells};
+use crate::{dvmql::query::tree::TreeNode, join::ResultTable};
+
+/// Writes the rows of the result table to a CSV file, one record at a time.
+///
+/// The header row holds the labels of the nodes marked for output.
+pub async fn write_csv(path: &str, tree: &TreeNode, table: &ResultTable) -> Result<()> {
+    info!("Writing CSV output to {}", path);
+    let columns = output_columns(tree, table)?;
+    let file = File::create(path)
+        .await
+        .with_context(|| format!("Failed to create file: {}", path))?;
+    let mut writer = csv_async::AsyncWriter::from_writer(file);
+    writer
+        .write_record(columns.iter().map(|column| column.label.as_str()))
+        .await?;
+    for (key, row) in &table.rows {
+        let cells = select_cells(&columns, key, row);
+        writer
+            .write_record(cells.iter().map(|values| format_cell(values)))
+            .await?;
+    }
+    writer.flush().await?;
+    debug!("Wrote {} rows to {}", table.rows.len(), path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use assert_fs::{prelude::*, TempDir};
+
+    use super::*;
+    use crate::test_util;
+
+    fn node(label: &str, children: Vec<TreeNode>, output: bool) -> TreeNode {
+        TreeNode {
+            output,
+            ..test_util::node(&label.to_lowercase(), label, children)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_write_csv() {
+        let tree = node(
+            "X000",
+            vec![
+                node("X001", vec![node("X002", vec![], true)], false),
+                node("X003", vec![], true),
+            ],
+            true,
+        );
+        let table = ResultTable {
+            columns: vec!["X001".into(), "X002".into(), "X003".into()],
+            rows: BTreeMap::from([
+                (
+                    "1".to_string(),
+                    vec![vec!["a".into()], vec!["b".into(), "c".into()], vec![]],
+                ),
+                (
+                    "2".to_string(),
+                    vec![vec!["d".into()], vec![], vec!["e".into()]],
+                ),
+            ]),
+        };
+        let dir = TempDir::new().unwrap();
+        let file = dir.child("output.csv");
+        write_csv(file.path().to_str().unwrap(), &tree, &table)
+            .await
+            .unwrap();
+        file.assert("X000,X002,X003\n1,\"b, c\",\n2,,e\n");
+    }
+
+    #[tokio::test]
+    async fn test_write_csv_rejects_output_node_missing_from_table() {
+        let tree = node("X000", vec![node("X001", vec![], true)], true);
+        let table = ResultTable {
+            columns: vec!["X002".into()],
+            rows: BTreeMap::new(),
+        };
+        let dir = TempDir::new().unwrap();
+        let file = dir.child("output.csv");
+        let err = write_csv(file.path().to_str().unwrap(), &tree, &table)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Node X001 is marked for output, but the result table has no column for it"
+        );
+        file.assert(predicates::path::missing());
+    }
+}
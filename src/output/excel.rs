@@ -0,0 +1,203 @@
+//! # Excel output
+//!
+//! This module contains the writer for the EXCEL output format.
+
+use anyhow::{bail, Context, Result};
+use rust_xlsxwriter::{ExcelDateTime, Format, Workbook, Worksheet};
+use tracing::{debug, info};
+
+use super::{format_cell, output_columns, select_cells, OutputColumn};
+use crate::{dvmql::query::tree::TreeNode, join::ResultTable};
+
+/// Maximum number of rows in a worksheet, including the header row.
+const SHEET_ROW_LIMIT: usize = 1_048_576;
+
+/// Writes the rows of the result table to an .xlsx workbook.
+///
+/// The header row holds the labels of the nodes marked for output. Results larger than the
+/// sheet row limit are split across multiple sheets when `split_sheets` is set, and rejected
+/// otherwise.
+pub fn write_excel(
+    path: &str,
+    tree: &TreeNode,
+    table: &ResultTable,
+    split_sheets: bool,
+) -> Result<()> {
+    info!("Writing EXCEL output to {}", path);
+    let mut workbook = build_workbook(tree, table, SHEET_ROW_LIMIT - 1, split_sheets)?;
+    workbook
+        .save(path)
+        .with_context(|| format!("Failed to write file: {}", path))?;
+    debug!("Wrote {} rows to {}", table.rows.len(), path);
+    Ok(())
+}
+
+/// Helper function for building a workbook holding at most `rows_per_sheet` rows per sheet.
+fn build_workbook(
+    tree: &TreeNode,
+    table: &ResultTable,
+    rows_per_sheet: usize,
+    split_sheets: bool,
+) -> Result<Workbook> {
+    if table.rows.len() > rows_per_sheet && !split_sheets {
+        bail!(
+            "Query produced {} rows, more than the {} rows a sheet can hold. Enable splitting results across sheets to write them.",
+            table.rows.len(),
+            rows_per_sheet
+        );
+    }
+    let columns = output_columns(tree, table)?;
+    let date_format = Format::new().set_num_format("yyyy-mm-dd");
+    let datetime_format = Format::new().set_num_format("yyyy-mm-dd hh:mm:ss");
+
+    let mut workbook = Workbook::new();
+    let rows: Vec<_> = table.rows.iter().collect();
+    let chunks: Vec<_> = if rows.is_empty() {
+        vec![&rows[..]]
+    } else {
+        rows.chunks(rows_per_sheet).collect()
+    };
+    for (i, chunk) in chunks.into_iter().enumerate() {
+        let worksheet = workbook.add_worksheet();
+        let name = match i {
+            0 => tree.label.clone(),
+            _ => format!("{} ({})", tree.label, i + 1),
+        };
+        worksheet.set_name(name)?;
+        write_header(worksheet, &columns)?;
+        for (row_num, (key, row)) in chunk.iter().enumerate() {
+            let row_num = u32::try_from(row_num + 1)?;
+            for (col_num, values) in select_cells(&columns, key, row).into_iter().enumerate() {
+                let col_num = u16::try_from(col_num)?;
+                match values {
+                    [] => (),
+                    [value] => {
+                        // Only numbers written back the same way, so that e.g. leading zeros
+                        // and digits past the f64 precision are kept as text
+                        if let Ok(number) = value.parse::<f64>() {
+                            if number.is_finite() && number.to_string() == *value {
+                                worksheet.write_number(row_num, col_num, number)?;
+                                continue;
+                            }
+                        }
+                        if looks_like_date(value) {
+                            if let Ok(datetime) = ExcelDateTime::parse_from_str(value) {
+                                let format = match value.len() {
+                                    10 => &date_format,
+                                    _ => &datetime_format,
+                                };
+                                worksheet.write_datetime_with_format(
+                                    row_num, col_num, datetime, format,
+                                )?;
+                                continue;
+                            }
+                        }
+                        worksheet.write_string(row_num, col_num, value)?;
+                    }
+                    values => {
+                        worksheet.write_string(row_num, col_num, format_cell(values))?;
+                    }
+                }
+            }
+        }
+    }
+    Ok(workbook)
+}
+
+fn write_header(worksheet: &mut Worksheet, columns: &[OutputColumn]) -> Result<()> {
+    for (col_num, column) in columns.iter().enumerate() {
+        worksheet.write_string(0, u16::try_from(col_num)?, &column.label)?;
+    }
+    Ok(())
+}
+
+/// Helper function for checking whether a value starts with a `YYYY-MM-DD` date.
+fn looks_like_date(value: &str) -> bool {
+    let bytes = value.as_bytes();
+    bytes.len() >= 10
+        && bytes[..4].iter().all(u8::is_ascii_digit)
+        && bytes[4] == b'-'
+        && bytes[5..7].iter().all(u8::is_ascii_digit)
+        && bytes[7] == b'-'
+        && bytes[8..10].iter().all(u8::is_ascii_digit)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use assert_fs::{prelude::*, TempDir};
+    use calamine::Data;
+
+    use super::*;
+    use crate::test_util::node;
+
+    fn get_tree() -> TreeNode {
+        node("root", "X000", vec![node("child", "X001", vec![])])
+    }
+
+    fn get_table(rows: usize) -> ResultTable {
+        ResultTable {
+            columns: vec!["X001".to_string()],
+            rows: (0..rows)
+                .map(|i| (i.to_string(), vec![vec!["2024-01-31".to_string()]]))
+                .collect::<BTreeMap<_, _>>(),
+        }
+    }
+
+    #[test]
+    fn test_looks_like_date() {
+        assert!(looks_like_date("2024-01-31"));
+        assert!(looks_like_date("2024-01-31 12:30:00"));
+        assert!(!looks_like_date("-5"));
+        assert!(!looks_like_date("12:30"));
+    }
+
+    #[test]
+    fn test_build_workbook_splits_sheets() {
+        let mut workbook = build_workbook(&get_tree(), &get_table(5), 2, true).unwrap();
+        assert_eq!(workbook.worksheets().len(), 3);
+        assert_eq!(workbook.worksheet_from_index(0).unwrap().name(), "X000");
+        assert_eq!(workbook.worksheet_from_index(2).unwrap().name(), "X000 (3)");
+    }
+
+    #[test]
+    fn test_build_workbook_rejects_too_many_rows() {
+        assert!(build_workbook(&get_tree(), &get_table(5), 2, false).is_err());
+    }
+
+    #[test]
+    fn test_write_excel() {
+        let table = ResultTable {
+            columns: vec!["X001".to_string()],
+            rows: BTreeMap::from([
+                ("1".to_string(), vec![vec!["2024-01-31".to_string()]]),
+                ("2".to_string(), vec![vec!["42.5".to_string()]]),
+                ("3".to_string(), vec![vec!["02134".to_string()]]),
+                (
+                    "4".to_string(),
+                    vec![vec!["12345678901234567890".to_string()]],
+                ),
+                ("5".to_string(), vec![vec!["1e3".to_string()]]),
+            ]),
+        };
+        let dir = TempDir::new().unwrap();
+        let file = dir.child("output.xlsx");
+        write_excel(file.path().to_str().unwrap(), &get_tree(), &table, false).unwrap();
+
+        let mut workbook = calamine::open_workbook_auto(file.path()).unwrap();
+        let range = calamine::Reader::worksheet_range(&mut workbook, "X000").unwrap();
+        let cell = |row: u32, col: u32| range.get_value((row, col)).unwrap().clone();
+        assert_eq!(cell(0, 0), Data::String("X000".to_string()));
+        assert_eq!(cell(0, 1), Data::String("X001".to_string()));
+        assert_eq!(cell(1, 0), Data::Float(1.0));
+        assert_eq!(
+            calamine::DataType::as_date(&cell(1, 1)),
+            chrono::NaiveDate::from_ymd_opt(2024, 1, 31)
+        );
+        assert_eq!(cell(2, 1), Data::Float(42.5));
+        assert_eq!(cell(3, 1), Data::String("02134".to_string()));
+        assert_eq!(cell(4, 1), Data::String("12345678901234567890".to_string()));
+        assert_eq!(cell(5, 1), Data::String("1e3".to_string()));
+    }
+}
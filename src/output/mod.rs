@@ -0,0 +1,99 @@
+//! # Output
+//!
+//! This module contains the writers for the results of a query.
+
+pub mod csv;
+pub mod excel;
+
+use anyhow::{Context, Result};
+use strum::EnumString;
+
+use crate::{dvmql::query::tree::TreeNode, join::ResultTable};
+
+/// Format in which the results of a query are written.
+#[derive(Debug, Default, Clone, Copy, PartialEq, EnumString)]
+#[strum(serialize_all = "UPPERCASE", ascii_case_insensitive)]
+pub enum OutputFormat {
+    #[default]
+    None,
+    Excel,
+    Csv,
+}
+
+/// Column of the result table selected for output.
+#[derive(Debug, PartialEq)]
+struct OutputColumn {
+    label: String,
+    source: Source,
+}
+
+/// Where the cells of an output column are read from in a row of the result table.
+#[derive(Debug, PartialEq)]
+enum Source {
+    /// The key of the row, for the root node.
+    Key,
+    /// The column at this index of the result table.
+    Column(usize),
+}
+
+/// Helper function for collecting the columns of the nodes marked for output, in tree order.
+///
+/// Fails if a node marked for output has no column in the result table.
+fn output_columns(tree: &TreeNode, table: &ResultTable) -> Result<Vec<OutputColumn>> {
+    let mut columns = vec![];
+    if tree.output {
+        columns.push(OutputColumn {
+            label: tree.label.clone(),
+            source: Source::Key,
+        });
+    }
+    collect_output_columns(&tree.children, table, &mut columns)?;
+    Ok(columns)
+}
+
+fn collect_output_columns(
+    nodes: &[TreeNode],
+    table: &ResultTable,
+    columns: &mut Vec<OutputColumn>,
+) -> Result<()> {
+    for node in nodes {
+        if node.output {
+            let index = table
+                .columns
+                .iter()
+                .position(|label| label == &node.label)
+                .with_context(|| {
+                    format!(
+                        "Node {} is marked for output, but the result table has no column for it",
+                        &node.label
+                    )
+                })?;
+            columns.push(OutputColumn {
+                label: node.label.clone(),
+                source: Source::Column(index),
+            });
+        }
+        collect_output_columns(&node.children, table, columns)?;
+    }
+    Ok(())
+}
+
+/// Helper function for selecting the cells of the output columns in a row.
+fn select_cells<'a>(
+    columns: &[OutputColumn],
+    key: &'a String,
+    row: &'a [Vec<String>],
+) -> Vec<&'a [String]> {
+    columns
+        .iter()
+        .map(|column| match column.source {
+            Source::Key => std::slice::from_ref(key),
+            Source::Column(index) => row.get(index).map_or(&[][..], |values| values.as_slice()),
+        })
+        .collect()
+}
+
+/// Helper function for formatting the value list of a cell.
+fn format_cell(values: &[String]) -> String {
+    values.join(", ")
+}
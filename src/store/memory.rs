@@ -0,0 +1,106 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use tracing::debug;
+
+use super::DvmStore;
+use crate::dvmql::dvm::{load_dvm_xml, Dvm, DvmEdge};
+use crate::load::edges::Edge;
+
+/// DVM held in memory, e.g. loaded directly from a DVM XML file.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    /// Names of the attributes.
+    attributes: HashSet<String>,
+    /// Edges keyed by the names of their head and tail attributes.
+    edges: HashMap<(String, String), Vec<Edge>>,
+}
+
+impl MemoryStore {
+    /// Loads the DVM XML file at `dvm_path`.
+    pub fn load(dvm_path: &str) -> Result<MemoryStore> {
+        let store = MemoryStore::from(load_dvm_xml(dvm_path)?);
+        debug!("Loaded DVM {} in memory", dvm_path);
+        Ok(store)
+    }
+}
+
+impl From<Dvm> for MemoryStore {
+    fn from(dvm: Dvm) -> MemoryStore {
+        let mut attributes = HashSet::new();
+        let mut edges: HashMap<(String, String), Vec<Edge>> = HashMap::new();
+        for edge in dvm.edges {
+            attributes.insert(edge.head.name.clone());
+            attributes.insert(edge.tail.name.clone());
+            edges
+                .entry((edge.head.name.clone(), edge.tail.name.clone()))
+                .or_default()
+                .push(Edge::from(edge));
+        }
+        MemoryStore { attributes, edges }
+    }
+}
+
+impl From<DvmEdge> for Edge {
+    fn from(edge: DvmEdge) -> Edge {
+        Edge {
+            datasource_name: edge.datasource,
+            key_pos: edge.key,
+            value_pos: edge.value,
+            query: Some(edge.query).filter(|query| !query.is_empty()),
+        }
+    }
+}
+
+#[async_trait]
+impl DvmStore for MemoryStore {
+    async fn edges(&self, head: &str, tail: &str) -> Result<Vec<Edge>> {
+        Ok(self
+            .edges
+            .get(&(head.to_string(), tail.to_string()))
+            .map(|edges| edges.iter().map(Edge::clone).collect())
+            .unwrap_or_default())
+    }
+
+    async fn has_attribute(&self, name: &str) -> Result<bool> {
+        Ok(self.attributes.contains(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::dvm;
+
+    #[tokio::test]
+    async fn test_memory_store_edges() {
+        let dvm = dvm(&[
+            ("a", "b", "ds1", "", 1, 2),
+            ("a", "b", "ds2", "SELECT 1", 2, 1),
+        ]);
+        let store = MemoryStore::from(dvm);
+
+        let edges = store.edges("a", "b").await.unwrap();
+        assert_eq!(
+            edges,
+            vec![
+                Edge {
+                    datasource_name: String::from("ds1"),
+                    key_pos: 1,
+                    value_pos: 2,
+                    query: None,
+                },
+                Edge {
+                    datasource_name: String::from("ds2"),
+                    key_pos: 2,
+                    value_pos: 1,
+                    query: Some(String::from("SELECT 1")),
+                },
+            ]
+        );
+        assert!(store.edges("b", "a").await.unwrap().is_empty());
+        assert!(store.has_attribute("b").await.unwrap());
+        assert!(!store.has_attribute("c").await.unwrap());
+    }
+}
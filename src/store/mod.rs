@@ -0,0 +1,26 @@
+//! # Store
+//!
+//! This module contains the backends the DVM graph is read from while executing queries.
+
+pub mod memory;
+pub mod neo4j;
+pub mod sqlite;
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::load::edges::Edge;
+
+pub use self::memory::MemoryStore;
+pub use self::neo4j::Neo4jStore;
+pub use self::sqlite::SqliteStore;
+
+/// Backend holding the attributes of the DVM and the `has` edges between them.
+#[async_trait]
+pub trait DvmStore: Send + Sync {
+    /// Returns the edges from the `head` attribute to the `tail` attribute.
+    async fn edges(&self, head: &str, tail: &str) -> Result<Vec<Edge>>;
+
+    /// Returns whether the DVM has an attribute named `name`.
+    async fn has_attribute(&self, name: &str) -> Result<bool>;
+}
@@ -0,0 +1,47 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use neo4rs::{query, Graph};
+
+use super::DvmStore;
+use crate::load::edges::Edge;
+
+const QUERY: &str = "MATCH (a:attribute{name: $nodeA})-[r:has]->(b:attribute{name: $nodeB}) RETURN r.datasource as datasource, r.query as query, r.key as key, r.value as value";
+
+const ATTRIBUTE_QUERY: &str = "MATCH (n:attribute{name: $name}) RETURN count(n) > 0 as found";
+
+/// DVM stored in a Neo4j graph.
+pub struct Neo4jStore {
+    graph: Graph,
+}
+
+impl Neo4jStore {
+    pub fn new(graph: Graph) -> Neo4jStore {
+        Neo4jStore { graph }
+    }
+}
+
+#[async_trait]
+impl DvmStore for Neo4jStore {
+    async fn edges(&self, head: &str, tail: &str) -> Result<Vec<Edge>> {
+        let mut result = self
+            .graph
+            .execute(query(QUERY).param("nodeA", head).param("nodeB", tail))
+            .await?;
+        let mut edges = vec![];
+        while let Some(row) = result.next().await? {
+            edges.push(row.to()?);
+        }
+        Ok(edges)
+    }
+
+    async fn has_attribute(&self, name: &str) -> Result<bool> {
+        let mut result = self
+            .graph
+            .execute(query(ATTRIBUTE_QUERY).param("name", name))
+            .await?;
+        match result.next().await? {
+            Some(row) => Ok(row.get("found")?),
+            None => Ok(false),
+        }
+    }
+}
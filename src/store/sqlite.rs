@@ -0,0 +1,234 @@
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use rusqlite::{params, Connection, OpenFlags};
+use tracing::debug;
+
+use super::DvmStore;
+use crate::dvmql::dvm::DvmEdge;
+use crate::load::edges::Edge;
+
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS attribute (
+        name TEXT PRIMARY KEY,
+        description TEXT NOT NULL DEFAULT ''
+    );
+    CREATE TABLE IF NOT EXISTS has (
+        head TEXT NOT NULL REFERENCES attribute (name),
+        tail TEXT NOT NULL REFERENCES attribute (name),
+        datasource TEXT NOT NULL,
+        query TEXT NOT NULL DEFAULT '',
+        key INTEGER NOT NULL,
+        value INTEGER NOT NULL,
+        PRIMARY KEY (head, tail, datasource)
+    );";
+
+/// DVM persisted in a single SQLite file, with an `attribute` table and a `has` table.
+///
+/// Clones share the same connection.
+#[derive(Clone)]
+pub struct SqliteStore {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStore {
+    /// Opens the SQLite file at `path`, creating it and its tables if missing.
+    pub fn open(path: &str) -> Result<SqliteStore> {
+        let conn = Connection::open(path)
+            .with_context(|| format!("Failed to open SQLite DVM {}", path))?;
+        conn.execute_batch(SCHEMA)
+            .with_context(|| format!("Failed to create the tables of SQLite DVM {}", path))?;
+        debug!("Opened SQLite DVM {}", path);
+        Ok(SqliteStore {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Opens the existing SQLite file at `path` for reading, without creating it or its tables.
+    pub fn open_read_only(path: &str) -> Result<SqliteStore> {
+        if !Path::new(path).is_file() {
+            bail!("SQLite DVM {} does not exist", path);
+        }
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .with_context(|| format!("Failed to open SQLite DVM {}", path))?;
+        let tables: u32 = conn
+            .query_row(
+                "SELECT count(*) FROM sqlite_master \
+                WHERE type = 'table' AND name IN ('attribute', 'has')",
+                [],
+                |row| row.get(0),
+            )
+            .with_context(|| format!("Failed to read SQLite DVM {}", path))?;
+        if tables != 2 {
+            bail!(
+                "SQLite DVM {} has no attribute and has tables. Load a DVM into it with dvm-to-neo4j --sqlite-path.",
+                path
+            );
+        }
+        debug!("Opened SQLite DVM {} read-only", path);
+        Ok(SqliteStore {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Deletes every attribute and edge.
+    pub fn clear(&self) -> Result<()> {
+        self.lock()?
+            .execute_batch("DELETE FROM has; DELETE FROM attribute;")?;
+        Ok(())
+    }
+
+    /// Stores a batch of edges and their nodes in a single transaction.
+    ///
+    /// Like loading into Neo4j, existing nodes keep their description and existing edges
+    /// are updated in place, so that loading the same DVM twice leaves the store unchanged.
+    pub fn store_edges(&self, edges: &[DvmEdge]) -> Result<()> {
+        let mut conn = self.lock()?;
+        let txn = conn.transaction()?;
+        {
+            let mut insert_node = txn.prepare(
+                "INSERT INTO attribute (name, description) VALUES (?1, ?2) \
+                ON CONFLICT (name) DO NOTHING",
+            )?;
+            let mut upsert_edge = txn.prepare(
+                "INSERT INTO has (head, tail, datasource, query, key, value) \
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6) \
+                ON CONFLICT (head, tail, datasource) DO UPDATE \
+                SET query = excluded.query, key = excluded.key, value = excluded.value",
+            )?;
+            for edge in edges {
+                insert_node.execute(params![edge.head.name, edge.head.description])?;
+                insert_node.execute(params![edge.tail.name, edge.tail.description])?;
+                upsert_edge.execute(params![
+                    edge.head.name,
+                    edge.tail.name,
+                    edge.datasource,
+                    edge.query,
+                    edge.key,
+                    edge.value
+                ])?;
+            }
+        }
+        txn.commit()
+            .with_context(|| format!("Error storing batch of {} edges", edges.len()))?;
+        Ok(())
+    }
+
+    /// Helper function for locking the connection.
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, Connection>> {
+        self.conn
+            .lock()
+            .map_err(|_| anyhow!("SQLite DVM connection is poisoned"))
+    }
+}
+
+#[async_trait]
+impl DvmStore for SqliteStore {
+    async fn edges(&self, head: &str, tail: &str) -> Result<Vec<Edge>> {
+        let store = self.clone();
+        let (head, tail) = (head.to_string(), tail.to_string());
+        tokio::task::spawn_blocking(move || {
+            let conn = store.lock()?;
+            let mut statement = conn.prepare_cached(
+                "SELECT datasource, query, key, value FROM has \
+                WHERE head = ?1 AND tail = ?2 ORDER BY rowid",
+            )?;
+            let edges = statement
+                .query_map(params![head, tail], |row| {
+                    Ok(Edge {
+                        datasource_name: row.get(0)?,
+                        query: Some(row.get::<_, String>(1)?).filter(|query| !query.is_empty()),
+                        key_pos: row.get(2)?,
+                        value_pos: row.get(3)?,
+                    })
+                })?
+                .collect::<rusqlite::Result<Vec<Edge>>>()?;
+            Ok(edges)
+        })
+        .await?
+    }
+
+    async fn has_attribute(&self, name: &str) -> Result<bool> {
+        let store = self.clone();
+        let name = name.to_string();
+        tokio::task::spawn_blocking(move || {
+            let conn = store.lock()?;
+            let found = conn.query_row(
+                "SELECT EXISTS (SELECT 1 FROM attribute WHERE name = ?1)",
+                params![name],
+                |row| row.get(0),
+            )?;
+            Ok(found)
+        })
+        .await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_fs::TempDir;
+
+    use super::*;
+    use crate::test_util::dvm;
+
+    #[tokio::test]
+    async fn test_sqlite_store_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("dvm.sqlite");
+        let dvm = dvm(&[("a", "b", "ds1", "", 1, 2)]);
+
+        let store = SqliteStore::open(path.to_str().unwrap()).unwrap();
+        store.store_edges(&dvm.edges).unwrap();
+        store.store_edges(&dvm.edges).unwrap();
+        drop(store);
+
+        // Reopen the file for reading, like a later query would
+        let store = SqliteStore::open_read_only(path.to_str().unwrap()).unwrap();
+        assert_eq!(
+            store.edges("a", "b").await.unwrap(),
+            vec![Edge {
+                datasource_name: String::from("ds1"),
+                key_pos: 1,
+                value_pos: 2,
+                query: None,
+            }]
+        );
+        assert!(store.edges("b", "a").await.unwrap().is_empty());
+        assert!(store.has_attribute("a").await.unwrap());
+        assert!(!store.has_attribute("c").await.unwrap());
+
+        drop(store);
+
+        let store = SqliteStore::open(path.to_str().unwrap()).unwrap();
+        store.clear().unwrap();
+        assert!(store.edges("a", "b").await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_sqlite_store_read_only_requires_an_existing_file() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("typo.sqlite");
+        let err = SqliteStore::open_read_only(path.to_str().unwrap())
+            .err()
+            .unwrap();
+        assert!(err.to_string().ends_with("does not exist"));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_sqlite_store_read_only_requires_the_tables() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("other.sqlite");
+        Connection::open(&path)
+            .unwrap()
+            .execute_batch("CREATE TABLE other (id INTEGER)")
+            .unwrap();
+        let err = SqliteStore::open_read_only(path.to_str().unwrap())
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("has no attribute and has tables"));
+    }
+}
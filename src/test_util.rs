@@ -0,0 +1,53 @@
+//! # Test utilities
+//!
+//! This module contains the query trees, DVMs and datasources shared by the tests of the
+//! other modules.
+
+use crate::dvmql::dvm::{Dvm, DvmEdge, DvmNode};
+use crate::dvmql::query::tree::TreeNode;
+use crate::load::{Csv, Datasource};
+
+/// Builds a node marked for output, without transformations or theta.
+pub fn node(name: &str, label: &str, children: Vec<TreeNode>) -> TreeNode {
+    TreeNode {
+        name: name.to_string(),
+        label: label.to_string(),
+        children,
+        transformations: vec![],
+        theta: None,
+        output: true,
+    }
+}
+
+/// Builds a DVM from (head, tail, datasource, query, key, value) edges.
+pub fn dvm(edges: &[(&str, &str, &str, &str, u32, u32)]) -> Dvm {
+    let dvm_node = |name: &str| DvmNode {
+        name: name.to_string(),
+        description: String::new(),
+    };
+    Dvm {
+        edges: edges
+            .iter()
+            .map(|&(head, tail, datasource, query, key, value)| DvmEdge {
+                head: dvm_node(head),
+                tail: dvm_node(tail),
+                datasource: datasource.to_string(),
+                query: query.to_string(),
+                key,
+                value,
+            })
+            .collect(),
+    }
+}
+
+/// Builds a CSV datasource with headers reading `filename` from the test data directory.
+pub fn csv_datasource(name: &str, filename: &str) -> Datasource {
+    Datasource::Csv(Csv {
+        id: 1,
+        name: name.to_string(),
+        filename: filename.to_string(),
+        path: concat!(env!("CARGO_MANIFEST_DIR"), "/test_data").to_string(),
+        delimiter: ',',
+        has_headers: true,
+    })
+}
@@ -1,7 +1,10 @@
+use anyhow::{bail, Result};
 use serde::Deserialize;
-use strum::EnumString;
+use strum::{Display, EnumString};
 
-#[derive(Deserialize, Default, Debug, PartialEq, EnumString)]
+use super::expression::Value;
+
+#[derive(Deserialize, Default, Debug, PartialEq, EnumString, Display)]
 #[strum(serialize_all = "lowercase")]
 pub enum AggregationType {
     Min,
@@ -12,3 +15,80 @@ pub enum AggregationType {
     #[default]
     Any,
 }
+
+impl AggregationType {
+    /// Collapses the values of a key into a single value.
+    ///
+    /// `Min` and `Max` compare numerically when every value is a number and lexicographically
+    /// otherwise, while `Sum` and `Average` fail on values that are not numbers.
+    pub fn aggregate(&self, values: &[String]) -> Result<String> {
+        let numbers = || -> Result<Vec<f64>> {
+            values
+                .iter()
+                .map(|value| match value.trim().parse::<f64>() {
+                    Ok(number) => Ok(number),
+                    Err(_) => bail!("Cannot aggregate \"{}\" as a number", value),
+                })
+                .collect()
+        };
+        let format_number = |number: f64| Value::Number(number).to_string();
+        Ok(match self {
+            AggregationType::Count => values.len().to_string(),
+            AggregationType::Any => values.first().cloned().unwrap_or_default(),
+            AggregationType::Sum => format_number(numbers()?.iter().sum()),
+            AggregationType::Average => {
+                let numbers = numbers()?;
+                if numbers.is_empty() {
+                    return Ok(String::new());
+                }
+                format_number(numbers.iter().sum::<f64>() / numbers.len() as f64)
+            }
+            AggregationType::Min | AggregationType::Max => match numbers() {
+                Ok(numbers) => {
+                    let extreme = match self {
+                        AggregationType::Min => numbers.into_iter().reduce(f64::min),
+                        _ => numbers.into_iter().reduce(f64::max),
+                    };
+                    extreme.map(format_number).unwrap_or_default()
+                }
+                Err(_) => {
+                    let extreme = match self {
+                        AggregationType::Min => values.iter().min(),
+                        _ => values.iter().max(),
+                    };
+                    extreme.cloned().unwrap_or_default()
+                }
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn test_aggregate_numbers() {
+        let values = strings(&["10", "2", "4.5"]);
+        assert_eq!(AggregationType::Min.aggregate(&values).unwrap(), "2");
+        assert_eq!(AggregationType::Max.aggregate(&values).unwrap(), "10");
+        assert_eq!(AggregationType::Sum.aggregate(&values).unwrap(), "16.5");
+        assert_eq!(AggregationType::Average.aggregate(&values).unwrap(), "5.5");
+        assert_eq!(AggregationType::Count.aggregate(&values).unwrap(), "3");
+        assert_eq!(AggregationType::Any.aggregate(&values).unwrap(), "10");
+    }
+
+    #[test]
+    fn test_aggregate_strings() {
+        let values = strings(&["b", "c", "a"]);
+        assert_eq!(AggregationType::Min.aggregate(&values).unwrap(), "a");
+        assert_eq!(AggregationType::Max.aggregate(&values).unwrap(), "c");
+        assert_eq!(AggregationType::Count.aggregate(&values).unwrap(), "3");
+        assert!(AggregationType::Sum.aggregate(&values).is_err());
+        assert!(AggregationType::Average.aggregate(&values).is_err());
+    }
+}
@@ -0,0 +1,798 @@
+//! # Expressions
+//!
+//! This module contains the parser and evaluator of the expressions used by transformations,
+//! e.g. `$X001$ > 5 and startswith($X002$, 'A')`.
+//!
+//! `$LABEL$` references are bound to the values of the nodes with the given labels. Values are
+//! text, so comparisons and arithmetic treat them as numbers whenever both sides parse as one.
+//!
+//! Supported functions:
+//!
+//! - `contains(s, sub)`, `startswith(s, prefix)`, `endswith(s, suffix)`, `regex(s, pattern)`
+//! - `upper(s)`, `lower(s)`, `trim(s)`, `length(s)`, `concat(s, ...)`
+//! - `substring(s, start[, length])`, with a 0-based `start` counted in characters
+//! - `int(x)`, `float(x)`, `string(x)` casts
+//! - `date_format(date, [input_format,] output_format)`, with strftime-style formats
+
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fmt::{Display, Write},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime};
+use regex::Regex;
+
+/// Values of the `$LABEL$` references of an expression, keyed by label.
+pub type Bindings<'a> = HashMap<&'a str, &'a str>;
+
+/// Value produced by evaluating an expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+}
+
+impl Value {
+    /// Returns the value as a number, parsing strings if needed.
+    fn as_number(&self) -> Option<f64> {
+        match self {
+            Value::Number(n) => Some(*n),
+            Value::String(s) => s.trim().parse().ok(),
+            _ => None,
+        }
+    }
+
+    fn expect_number(&self) -> Result<f64> {
+        self.as_number()
+            .with_context(|| format!("Expected a number, found \"{}\"", self))
+    }
+
+    fn expect_bool(&self) -> Result<bool> {
+        match self {
+            Value::Bool(b) => Ok(*b),
+            _ => bail!("Expected a boolean, found \"{}\"", self),
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Null => Ok(()),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => write!(f, "{}", *n as i64),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::String(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+/// Parsed expression.
+#[derive(Debug)]
+pub struct Expression {
+    text: String,
+    root: Expr,
+}
+
+impl FromStr for Expression {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let tokens = tokenize(text).with_context(|| format!("Invalid expression: {}", text))?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let root = parser
+            .parse()
+            .with_context(|| format!("Invalid expression: {}", text))?;
+        Ok(Self {
+            text: text.to_owned(),
+            root,
+        })
+    }
+}
+
+impl Expression {
+    /// Evaluates the expression with the given reference bindings.
+    pub fn evaluate(&self, bindings: &Bindings) -> Result<Value> {
+        self.root
+            .evaluate(bindings)
+            .with_context(|| format!("Failed to evaluate expression: {}", self.text))
+    }
+
+    /// Evaluates the expression as a condition, which must produce a boolean.
+    pub fn evaluate_condition(&self, bindings: &Bindings) -> Result<bool> {
+        self.evaluate(bindings)?
+            .expect_bool()
+            .with_context(|| format!("Condition must be a boolean: {}", self.text))
+    }
+
+    /// Returns the labels referenced by the expression.
+    pub fn references(&self) -> Vec<&str> {
+        let mut references = vec![];
+        self.root.collect_references(&mut references);
+        references
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    String(String),
+    Reference(String),
+    Ident(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+    Comma,
+}
+
+/// Operators, longest first so that `<=` is matched before `<`.
+const OPERATORS: [&str; 16] = [
+    "==", "!=", "<>", "<=", ">=", "&&", "||", "=", "<", ">", "+", "-", "*", "/", "%", "!",
+];
+
+fn tokenize(text: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut chars = text.char_indices().peekable();
+    while let Some(&(i, c)) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' | ',' => {
+                chars.next();
+                tokens.push(match c {
+                    '(' => Token::LParen,
+                    ')' => Token::RParen,
+                    _ => Token::Comma,
+                });
+            }
+            '$' => {
+                chars.next();
+                let mut label = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '$')) => break,
+                        Some((_, c)) => label.push(c),
+                        None => bail!("Unterminated reference at position {}", i),
+                    }
+                }
+                tokens.push(Token::Reference(label.trim().to_owned()));
+            }
+            '\'' | '"' => {
+                chars.next();
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, escaped)) => s.push(escaped),
+                            None => bail!("Unterminated string at position {}", i),
+                        },
+                        Some((_, q)) if q == c => break,
+                        Some((_, ch)) => s.push(ch),
+                        None => bail!("Unterminated string at position {}", i),
+                    }
+                }
+                tokens.push(Token::String(s));
+            }
+            c if c.is_ascii_digit() || c == '.' => {
+                let mut number = String::new();
+                while let Some(&(_, c)) = chars.peek() {
+                    if !(c.is_ascii_digit() || c == '.') {
+                        break;
+                    }
+                    number.push(c);
+                    chars.next();
+                }
+                let number = number
+                    .parse()
+                    .map_err(|_| anyhow!("Invalid number {} at position {}", number, i))?;
+                tokens.push(Token::Number(number));
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut ident = String::new();
+                while let Some(&(_, c)) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_') {
+                        break;
+                    }
+                    ident.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Ident(ident));
+            }
+            _ => {
+                let op = OPERATORS
+                    .iter()
+                    .find(|op| text[i..].starts_with(*op))
+                    .with_context(|| format!("Unexpected character '{}' at position {}", c, i))?;
+                for _ in 0..op.len() {
+                    chars.next();
+                }
+                tokens.push(Token::Op(op));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BinaryOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum UnaryOp {
+    Not,
+    Neg,
+}
+
+#[derive(Debug)]
+enum Expr {
+    Literal(Value),
+    Reference(String),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+    /// Regex match against a pattern compiled at parse time.
+    Matches(Box<Expr>, Regex),
+}
+
+/// Recursive descent parser, from the lowest to the highest precedence:
+/// `or`, `and`, `not`, comparisons, `+ -`, `* / %`, unary `-` and primaries.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn parse(&mut self) -> Result<Expr> {
+        let expr = self.parse_or()?;
+        match self.tokens.get(self.pos) {
+            Some(token) => bail!("Unexpected {:?} after end of expression", token),
+            None => Ok(expr),
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Ident(ident)) if ident.eq_ignore_ascii_case(keyword) => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn eat_op(&mut self, ops: &[&str]) -> Option<&'static str> {
+        match self.peek() {
+            Some(Token::Op(op)) if ops.contains(op) => {
+                let op = *op;
+                self.pos += 1;
+                Some(op)
+            }
+            _ => None,
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Expr> {
+        let mut lhs = self.parse_and()?;
+        while self.eat_keyword("or") || self.eat_op(&["||"]).is_some() {
+            let rhs = self.parse_and()?;
+            lhs = Expr::Binary(BinaryOp::Or, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr> {
+        let mut lhs = self.parse_not()?;
+        while self.eat_keyword("and") || self.eat_op(&["&&"]).is_some() {
+            let rhs = self.parse_not()?;
+            lhs = Expr::Binary(BinaryOp::And, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr> {
+        if self.eat_keyword("not") || self.eat_op(&["!"]).is_some() {
+            let expr = self.parse_not()?;
+            return Ok(Expr::Unary(UnaryOp::Not, Box::new(expr)));
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr> {
+        let lhs = self.parse_additive()?;
+        let op = match self.eat_op(&["==", "=", "!=", "<>", "<", "<=", ">", ">="]) {
+            Some("==" | "=") => BinaryOp::Eq,
+            Some("!=" | "<>") => BinaryOp::Ne,
+            Some("<") => BinaryOp::Lt,
+            Some("<=") => BinaryOp::Le,
+            Some(">") => BinaryOp::Gt,
+            Some(">=") => BinaryOp::Ge,
+            _ => return Ok(lhs),
+        };
+        let rhs = self.parse_additive()?;
+        Ok(Expr::Binary(op, Box::new(lhs), Box::new(rhs)))
+    }
+
+    fn parse_additive(&mut self) -> Result<Expr> {
+        let mut lhs = self.parse_multiplicative()?;
+        while let Some(op) = self.eat_op(&["+", "-"]) {
+            let op = match op {
+                "+" => BinaryOp::Add,
+                _ => BinaryOp::Sub,
+            };
+            let rhs = self.parse_multiplicative()?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_multiplicative(&mut self) -> Result<Expr> {
+        let mut lhs = self.parse_unary()?;
+        while let Some(op) = self.eat_op(&["*", "/", "%"]) {
+            let op = match op {
+                "*" => BinaryOp::Mul,
+                "/" => BinaryOp::Div,
+                _ => BinaryOp::Rem,
+            };
+            let rhs = self.parse_unary()?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr> {
+        if self.eat_op(&["-"]).is_some() {
+            let expr = self.parse_unary()?;
+            return Ok(Expr::Unary(UnaryOp::Neg, Box::new(expr)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(Expr::Literal(Value::Number(n))),
+            Some(Token::String(s)) => Ok(Expr::Literal(Value::String(s))),
+            Some(Token::Reference(label)) => Ok(Expr::Reference(label)),
+            Some(Token::LParen) => {
+                let expr = self.parse_or()?;
+                match self.next() {
+                    Some(Token::RParen) => Ok(expr),
+                    _ => bail!("Expected ')'"),
+                }
+            }
+            Some(Token::Ident(ident)) => match ident.to_lowercase().as_str() {
+                "true" => Ok(Expr::Literal(Value::Bool(true))),
+                "false" => Ok(Expr::Literal(Value::Bool(false))),
+                "null" => Ok(Expr::Literal(Value::Null)),
+                name => {
+                    let args = self.parse_args(name)?;
+                    build_call(name, args)
+                }
+            },
+            Some(token) => bail!("Unexpected {:?}", token),
+            None => bail!("Unexpected end of expression"),
+        }
+    }
+
+    fn parse_args(&mut self, name: &str) -> Result<Vec<Expr>> {
+        match self.next() {
+            Some(Token::LParen) => (),
+            _ => bail!("Expected '(' after function {}", name),
+        }
+        let mut args = vec![];
+        if self.peek() == Some(&Token::RParen) {
+            self.pos += 1;
+            return Ok(args);
+        }
+        loop {
+            args.push(self.parse_or()?);
+            match self.next() {
+                Some(Token::Comma) => continue,
+                Some(Token::RParen) => return Ok(args),
+                _ => bail!("Expected ',' or ')' in arguments of function {}", name),
+            }
+        }
+    }
+}
+
+/// Helper function for checking the arguments of a function call.
+fn build_call(name: &str, mut args: Vec<Expr>) -> Result<Expr> {
+    let (min_args, max_args) = match name {
+        "upper" | "lower" | "trim" | "length" | "int" | "float" | "string" => (1, 1),
+        "contains" | "startswith" | "endswith" | "regex" | "matches" => (2, 2),
+        "substring" | "date_format" => (2, 3),
+        "concat" => (1, usize::MAX),
+        _ => bail!("Unknown function {}", name),
+    };
+    if args.len() < min_args || args.len() > max_args {
+        let expected = match (min_args, max_args) {
+            (min, max) if min == max => min.to_string(),
+            (min, usize::MAX) => format!("at least {}", min),
+            (min, max) => format!("{} to {}", min, max),
+        };
+        bail!(
+            "Function {} expects {} arguments, found {}",
+            name,
+            expected,
+            args.len()
+        );
+    }
+    if let ("regex" | "matches", Some(Expr::Literal(Value::String(pattern)))) = (name, args.get(1))
+    {
+        let regex = Regex::new(pattern)?;
+        let value = args.swap_remove(0);
+        return Ok(Expr::Matches(Box::new(value), regex));
+    }
+    Ok(Expr::Call(name.to_owned(), args))
+}
+
+impl Expr {
+    fn evaluate(&self, bindings: &Bindings) -> Result<Value> {
+        match self {
+            Expr::Literal(value) => Ok(value.clone()),
+            Expr::Reference(label) => bindings
+                .get(label.as_str())
+                .map(|value| Value::String(value.to_string()))
+                .with_context(|| format!("Unknown reference ${}$", label)),
+            Expr::Unary(UnaryOp::Not, expr) => {
+                Ok(Value::Bool(!expr.evaluate(bindings)?.expect_bool()?))
+            }
+            Expr::Unary(UnaryOp::Neg, expr) => {
+                Ok(Value::Number(-expr.evaluate(bindings)?.expect_number()?))
+            }
+            Expr::Binary(BinaryOp::Or, lhs, rhs) => Ok(Value::Bool(
+                lhs.evaluate(bindings)?.expect_bool()? || rhs.evaluate(bindings)?.expect_bool()?,
+            )),
+            Expr::Binary(BinaryOp::And, lhs, rhs) => Ok(Value::Bool(
+                lhs.evaluate(bindings)?.expect_bool()? && rhs.evaluate(bindings)?.expect_bool()?,
+            )),
+            Expr::Binary(op, lhs, rhs) => {
+                let lhs = lhs.evaluate(bindings)?;
+                let rhs = rhs.evaluate(bindings)?;
+                evaluate_binary(*op, &lhs, &rhs)
+            }
+            Expr::Call(name, args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| arg.evaluate(bindings))
+                    .collect::<Result<Vec<Value>>>()?;
+                call(name, &args)
+            }
+            Expr::Matches(value, regex) => Ok(Value::Bool(
+                regex.is_match(&value.evaluate(bindings)?.to_string()),
+            )),
+        }
+    }
+
+    fn collect_references<'a>(&'a self, references: &mut Vec<&'a str>) {
+        match self {
+            Expr::Literal(_) => (),
+            Expr::Reference(label) => references.push(label),
+            Expr::Unary(_, expr) | Expr::Matches(expr, _) => expr.collect_references(references),
+            Expr::Binary(_, lhs, rhs) => {
+                lhs.collect_references(references);
+                rhs.collect_references(references);
+            }
+            Expr::Call(_, args) => args
+                .iter()
+                .for_each(|arg| arg.collect_references(references)),
+        }
+    }
+}
+
+fn evaluate_binary(op: BinaryOp, lhs: &Value, rhs: &Value) -> Result<Value> {
+    let ordering = || compare(lhs, rhs);
+    Ok(match op {
+        BinaryOp::Eq => Value::Bool(ordering() == Some(Ordering::Equal)),
+        BinaryOp::Ne => Value::Bool(ordering() != Some(Ordering::Equal)),
+        BinaryOp::Lt => Value::Bool(ordering() == Some(Ordering::Less)),
+        BinaryOp::Le => Value::Bool(matches!(ordering(), Some(Ordering::Less | Ordering::Equal))),
+        BinaryOp::Gt => Value::Bool(ordering() == Some(Ordering::Greater)),
+        BinaryOp::Ge => Value::Bool(matches!(
+            ordering(),
+            Some(Ordering::Greater | Ordering::Equal)
+        )),
+        BinaryOp::Add => match (lhs.as_number(), rhs.as_number()) {
+            (Some(a), Some(b)) => Value::Number(a + b),
+            _ => Value::String(format!("{}{}", lhs, rhs)),
+        },
+        BinaryOp::Sub => Value::Number(lhs.expect_number()? - rhs.expect_number()?),
+        BinaryOp::Mul => Value::Number(lhs.expect_number()? * rhs.expect_number()?),
+        BinaryOp::Div | BinaryOp::Rem => {
+            let (a, b) = (lhs.expect_number()?, rhs.expect_number()?);
+            if b == 0.0 {
+                bail!("Division by zero");
+            }
+            match op {
+                BinaryOp::Div => Value::Number(a / b),
+                _ => Value::Number(a % b),
+            }
+        }
+        BinaryOp::Or | BinaryOp::And => unreachable!("handled with short-circuiting"),
+    })
+}
+
+/// Helper function for comparing two values, numerically when both are numbers.
+///
+/// Null is only equal to null and is not ordered against other values.
+fn compare(lhs: &Value, rhs: &Value) -> Option<Ordering> {
+    match (lhs, rhs) {
+        (Value::Null, Value::Null) => Some(Ordering::Equal),
+        (Value::Null, _) | (_, Value::Null) => None,
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        _ => match (lhs.as_number(), rhs.as_number()) {
+            (Some(a), Some(b)) => a.partial_cmp(&b),
+            _ => Some(lhs.to_string().cmp(&rhs.to_string())),
+        },
+    }
+}
+
+fn call(name: &str, args: &[Value]) -> Result<Value> {
+    let text = |i: usize| args[i].to_string();
+    Ok(match name {
+        "contains" => Value::Bool(text(0).contains(&text(1))),
+        "startswith" => Value::Bool(text(0).starts_with(&text(1))),
+        "endswith" => Value::Bool(text(0).ends_with(&text(1))),
+        "regex" | "matches" => Value::Bool(Regex::new(&text(1))?.is_match(&text(0))),
+        "upper" => Value::String(text(0).to_uppercase()),
+        "lower" => Value::String(text(0).to_lowercase()),
+        "trim" => Value::String(text(0).trim().to_owned()),
+        "length" => Value::Number(text(0).chars().count() as f64),
+        "concat" => Value::String(args.iter().map(Value::to_string).collect()),
+        "substring" => {
+            let start = to_index(&args[1])?;
+            let s = text(0);
+            let chars = s.chars().skip(start);
+            Value::String(match args.get(2) {
+                Some(length) => chars.take(to_index(length)?).collect(),
+                None => chars.collect(),
+            })
+        }
+        "int" => Value::Number(args[0].expect_number()?.trunc()),
+        "float" => Value::Number(args[0].expect_number()?),
+        "string" => Value::String(text(0)),
+        "date_format" => {
+            let (input_format, output_format) = match args.len() {
+                3 => (Some(text(1)), text(2)),
+                _ => (None, text(1)),
+            };
+            Value::String(format_date(
+                &text(0),
+                input_format.as_deref(),
+                &output_format,
+            )?)
+        }
+        _ => bail!("Unknown function {}", name),
+    })
+}
+
+/// Helper function for converting a value to a non-negative index.
+fn to_index(value: &Value) -> Result<usize> {
+    let n = value.expect_number()?;
+    if n < 0.0 || n.fract() != 0.0 {
+        bail!("Expected a non-negative integer, found \"{}\"", value);
+    }
+    Ok(n as usize)
+}
+
+/// Formats recognized when `date_format` is not given the format of its input.
+const DATE_FORMATS: [&str; 4] = [
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M:%S%.f",
+];
+
+/// Helper function for reformatting a date or datetime with strftime-style formats.
+fn format_date(value: &str, input_format: Option<&str>, output_format: &str) -> Result<String> {
+    let value = value.trim();
+    let datetime = match input_format {
+        Some(format) => NaiveDateTime::parse_from_str(value, format)
+            .ok()
+            .or_else(|| {
+                NaiveDate::parse_from_str(value, format)
+                    .ok()
+                    .map(|date| date.and_time(NaiveTime::MIN))
+            }),
+        None => DATE_FORMATS
+            .iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+            .or_else(|| {
+                DateTime::parse_from_rfc3339(value)
+                    .ok()
+                    .map(|dt| dt.naive_local())
+            })
+            .or_else(|| {
+                NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                    .ok()
+                    .map(|date| date.and_time(NaiveTime::MIN))
+            }),
+    }
+    .with_context(|| format!("Failed to parse date \"{}\"", value))?;
+    let mut formatted = String::new();
+    write!(formatted, "{}", datetime.format(output_format))
+        .map_err(|_| anyhow!("Invalid date format \"{}\"", output_format))?;
+    Ok(formatted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(text: &str, bindings: &[(&str, &str)]) -> Result<Value> {
+        let bindings: Bindings = bindings.iter().cloned().collect();
+        Expression::from_str(text)?.evaluate(&bindings)
+    }
+
+    #[test]
+    fn test_comparisons() {
+        let bindings = [("X001", "10"), ("X002", "abc")];
+        assert_eq!(eval("$X001$ > 5", &bindings).unwrap(), Value::Bool(true));
+        assert_eq!(
+            eval("$X001$ <= 9.5", &bindings).unwrap(),
+            Value::Bool(false)
+        );
+        assert_eq!(
+            eval("$X001$ = '10.0'", &bindings).unwrap(),
+            Value::Bool(true)
+        );
+        assert_eq!(
+            eval("$X002$ != 'abc'", &bindings).unwrap(),
+            Value::Bool(false)
+        );
+        assert_eq!(
+            eval("$X002$ < 'abd'", &bindings).unwrap(),
+            Value::Bool(true)
+        );
+    }
+
+    #[test]
+    fn test_boolean_logic() {
+        let bindings = [("X001", "10")];
+        assert_eq!(
+            eval("$X001$ > 5 and not ($X001$ > 20)", &bindings).unwrap(),
+            Value::Bool(true)
+        );
+        assert_eq!(
+            eval("$X001$ < 5 || $X001$ == 10", &bindings).unwrap(),
+            Value::Bool(true)
+        );
+        assert!(eval("$X001$ and true", &bindings).is_err());
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let bindings = [("X001", "10"), ("X002", "4")];
+        assert_eq!(
+            eval("$X001$ + $X002$ * 2", &bindings).unwrap(),
+            Value::Number(18.0)
+        );
+        assert_eq!(
+            eval("-($X001$ - $X002$) / 4", &bindings).unwrap(),
+            Value::Number(-1.5)
+        );
+        assert_eq!(eval("$X001$ % 3", &bindings).unwrap(), Value::Number(1.0));
+        assert!(eval("$X001$ / 0", &bindings).is_err());
+    }
+
+    #[test]
+    fn test_string_predicates() {
+        let bindings = [("X001", "Georgina.Hull@yopmail.com")];
+        assert_eq!(
+            eval("contains($X001$, '@')", &bindings).unwrap(),
+            Value::Bool(true)
+        );
+        assert_eq!(
+            eval(
+                "startswith($X001$, \"Geo\") and endswith($X001$, '.com')",
+                &bindings
+            )
+            .unwrap(),
+            Value::Bool(true)
+        );
+        assert_eq!(
+            eval("regex($X001$, '^[A-Z][a-z]+\\\\.Hull')", &bindings).unwrap(),
+            Value::Bool(true)
+        );
+    }
+
+    #[test]
+    fn test_invalid_expressions() {
+        assert!(Expression::from_str("$X001$ >").is_err());
+        assert!(Expression::from_str("$X001 > 5").is_err());
+        assert!(Expression::from_str("unknown($X001$)").is_err());
+        assert!(Expression::from_str("contains($X001$)").is_err());
+        assert!(Expression::from_str("regex($X001$, '(')").is_err());
+        assert!(eval("$X002$ > 5", &[("X001", "1")]).is_err());
+    }
+
+    #[test]
+    fn test_references() {
+        let expression = Expression::from_str("$X001$ > $X002$ and contains($X003$, 'a')").unwrap();
+        assert_eq!(expression.references(), vec!["X001", "X002", "X003"]);
+    }
+
+    #[test]
+    fn test_string_functions() {
+        let bindings = [("X001", "  Georgina "), ("X002", "Hull")];
+        assert_eq!(
+            eval("upper(trim($X001$))", &bindings).unwrap(),
+            Value::String("GEORGINA".to_string())
+        );
+        assert_eq!(
+            eval("concat(lower(trim($X001$)), '.', lower($X002$))", &bindings).unwrap(),
+            Value::String("georgina.hull".to_string())
+        );
+        assert_eq!(
+            eval("substring($X002$, 1, 2)", &bindings).unwrap(),
+            Value::String("ul".to_string())
+        );
+        assert_eq!(
+            eval("substring($X002$, 2)", &bindings).unwrap(),
+            Value::String("ll".to_string())
+        );
+        assert_eq!(
+            eval("length($X002$)", &bindings).unwrap(),
+            Value::Number(4.0)
+        );
+        assert!(eval("substring($X002$, -1)", &bindings).is_err());
+        assert!(Expression::from_str("upper($X001$, $X002$)").is_err());
+    }
+
+    #[test]
+    fn test_casts() {
+        let bindings = [("X001", "10.7")];
+        assert_eq!(eval("int($X001$)", &bindings).unwrap().to_string(), "10");
+        assert_eq!(
+            eval("float($X001$) * 2", &bindings).unwrap(),
+            Value::Number(21.4)
+        );
+        assert_eq!(
+            eval("concat(string(int($X001$)), 'kg')", &bindings).unwrap(),
+            Value::String("10kg".to_string())
+        );
+        assert!(eval("int('abc')", &bindings).is_err());
+    }
+
+    #[test]
+    fn test_date_format() {
+        let bindings = [("X001", "2024-01-31 12:30:00"), ("X002", "31/01/2024")];
+        assert_eq!(
+            eval("date_format($X001$, '%d.%m.%Y')", &bindings).unwrap(),
+            Value::String("31.01.2024".to_string())
+        );
+        assert_eq!(
+            eval("date_format($X002$, '%d/%m/%Y', '%Y-%m-%d')", &bindings).unwrap(),
+            Value::String("2024-01-31".to_string())
+        );
+        assert!(eval("date_format($X002$, '%Y')", &bindings).is_err());
+    }
+}
@@ -1,7 +1,18 @@
 pub mod aggregate;
+pub mod expression;
 
+use std::{
+    collections::{BTreeMap, HashMap},
+    str::FromStr,
+};
+
+use anyhow::{Context, Result};
 use serde::Deserialize;
 use strum::EnumString;
+use tracing::trace;
+
+use self::expression::{Bindings, Expression};
+use crate::dvmql::query::tree::TreeNode;
 
 #[derive(Deserialize, Debug, PartialEq, EnumString)]
 #[strum(serialize_all = "lowercase")]
@@ -10,3 +21,266 @@ pub enum Transformation {
     Filter(String),
     Map(String),
 }
+
+/// Applies the transformations of a node to its values, in order.
+///
+/// The values are keyed by the values of the parent node, so `$LABEL$` references can refer
+/// both to the node and to its parent.
+///
+/// Returns the keys dropped because their values could not be aggregated, along with the reason.
+pub fn apply_transformations(
+    transformations: &[Transformation],
+    label: &str,
+    parent_label: &str,
+    values: &mut HashMap<String, Vec<String>>,
+) -> Result<Vec<(String, anyhow::Error)>> {
+    let mut failures = vec![];
+    for transformation in transformations {
+        trace!("Applying {:?} to node {}", transformation, label);
+        match transformation {
+            Transformation::Filter(filter) => {
+                filter_values(filter, label, parent_label, values)
+                    .with_context(|| format!("Failed to filter node {}", label))?;
+            }
+            Transformation::Map(map) => {
+                map_values(map, label, parent_label, values)
+                    .with_context(|| format!("Failed to map node {}", label))?;
+            }
+            Transformation::Aggregate(aggregation) => {
+                failures.extend(aggregate_values(aggregation, label, values));
+            }
+        }
+    }
+    Ok(failures)
+}
+
+/// Applies the filters of the root node to the keys of its rows.
+///
+/// The root node has no parent, so `$LABEL$` references can only refer to the root node itself.
+pub fn apply_root_filters<V>(
+    transformations: &[Transformation],
+    label: &str,
+    rows: &mut BTreeMap<String, V>,
+) -> Result<()> {
+    for transformation in transformations {
+        let Transformation::Filter(filter) = transformation else {
+            continue;
+        };
+        trace!("Applying {:?} to root node {}", transformation, label);
+        let expression = Expression::from_str(filter)
+            .with_context(|| format!("Failed to filter node {}", label))?;
+        let mut result = Ok(());
+        rows.retain(|key, _| {
+            if result.is_err() {
+                return false;
+            }
+            expression
+                .evaluate_condition(&Bindings::from([(label, key.as_str())]))
+                .unwrap_or_else(|e| {
+                    result = Err(e);
+                    false
+                })
+        });
+        result.with_context(|| format!("Failed to filter node {}", label))?;
+    }
+    Ok(())
+}
+
+/// Returns the problem with a transformation of `node` that cannot be applied, if any.
+///
+/// The rows of a node's children are keyed by its original values, so maps and aggregations,
+/// which replace them, are only supported on nodes without children. The root node has no
+/// parent to key its values by, so only its filters are supported.
+pub fn unsupported_transformation(node: &TreeNode, is_root: bool) -> Option<String> {
+    let reason = if is_root {
+        "the root node"
+    } else if !node.children.is_empty() {
+        "a node with children, whose rows are keyed by its original values"
+    } else {
+        return None;
+    };
+    node.transformations
+        .iter()
+        .find_map(|transformation| match transformation {
+            Transformation::Map(map) => Some(format!(
+                "Node {}: map \"{}\" is not supported on {}",
+                &node.label, map, reason
+            )),
+            Transformation::Aggregate(aggregation) => Some(format!(
+                "Node {}: aggregate \"{}\" is not supported on {}",
+                &node.label, aggregation, reason
+            )),
+            Transformation::Filter(_) => None,
+        })
+}
+
+/// Helper function for keeping only the values that satisfy the filter expression.
+fn filter_values(
+    filter: &str,
+    label: &str,
+    parent_label: &str,
+    values: &mut HashMap<String, Vec<String>>,
+) -> Result<()> {
+    let expression = Expression::from_str(filter)?;
+    for (key, key_values) in values.iter_mut() {
+        let mut result = Ok(());
+        key_values.retain(|value| {
+            if result.is_err() {
+                return false;
+            }
+            let bindings = Bindings::from([(parent_label, key.as_str()), (label, value.as_str())]);
+            expression
+                .evaluate_condition(&bindings)
+                .unwrap_or_else(|e| {
+                    result = Err(e);
+                    false
+                })
+        });
+        result?;
+    }
+    values.retain(|_, key_values| !key_values.is_empty());
+    Ok(())
+}
+
+/// Helper function for collapsing the values of every key into a single value.
+///
+/// Keys whose values cannot be aggregated are dropped without failing the query, and
+/// returned along with the reason.
+fn aggregate_values(
+    aggregation: &aggregate::AggregationType,
+    label: &str,
+    values: &mut HashMap<String, Vec<String>>,
+) -> Vec<(String, anyhow::Error)> {
+    let mut failures = vec![];
+    values.retain(|key, key_values| match aggregation.aggregate(key_values) {
+        Ok(value) => {
+            *key_values = vec![value];
+            true
+        }
+        Err(e) => {
+            failures.push((
+                key.clone(),
+                e.context(format!(
+                    "Failed to aggregate node {} with {}",
+                    label, aggregation
+                )),
+            ));
+            false
+        }
+    });
+    failures.sort_by(|(a, _), (b, _)| a.cmp(b));
+    failures
+}
+
+/// Helper function for replacing every value with the result of the map expression.
+fn map_values(
+    map: &str,
+    label: &str,
+    parent_label: &str,
+    values: &mut HashMap<String, Vec<String>>,
+) -> Result<()> {
+    let expression = Expression::from_str(map)?;
+    for (key, key_values) in values.iter_mut() {
+        for value in key_values.iter_mut() {
+            let bindings = Bindings::from([(parent_label, key.as_str()), (label, value.as_str())]);
+            *value = expression.evaluate(&bindings)?.to_string();
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_values() -> HashMap<String, Vec<String>> {
+        HashMap::from([
+            ("a".to_string(), vec!["1".to_string(), "7".to_string()]),
+            ("b".to_string(), vec!["3".to_string()]),
+        ])
+    }
+
+    #[test]
+    fn test_apply_filter() {
+        let mut values = get_values();
+        let transformations = vec![Transformation::Filter("$X001$ > 5".to_string())];
+        apply_transformations(&transformations, "X001", "X000", &mut values).unwrap();
+        assert_eq!(
+            values,
+            HashMap::from([("a".to_string(), vec!["7".to_string()])])
+        );
+    }
+
+    #[test]
+    fn test_apply_filter_on_parent_value() {
+        let mut values = get_values();
+        let transformations = vec![Transformation::Filter("$X000$ = 'b'".to_string())];
+        apply_transformations(&transformations, "X001", "X000", &mut values).unwrap();
+        assert_eq!(
+            values,
+            HashMap::from([("b".to_string(), vec!["3".to_string()])])
+        );
+    }
+
+    #[test]
+    fn test_apply_invalid_filter() {
+        let mut values = get_values();
+        let transformations = vec![Transformation::Filter("$X001$ + 1".to_string())];
+        assert!(apply_transformations(&transformations, "X001", "X000", &mut values).is_err());
+    }
+
+    #[test]
+    fn test_apply_map_then_filter() {
+        let mut values = get_values();
+        let transformations = vec![
+            Transformation::Map("$X001$ * 2".to_string()),
+            Transformation::Filter("$X001$ > 5".to_string()),
+        ];
+        apply_transformations(&transformations, "X001", "X000", &mut values).unwrap();
+        assert_eq!(
+            values,
+            HashMap::from([
+                ("a".to_string(), vec!["14".to_string()]),
+                ("b".to_string(), vec!["6".to_string()]),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_apply_aggregate_drops_invalid_keys() {
+        let mut values = get_values();
+        values.insert("c".to_string(), vec!["x".to_string()]);
+        values.insert("d".to_string(), vec!["2".to_string(), "y".to_string()]);
+        let transformations = vec![Transformation::Aggregate(aggregate::AggregationType::Sum)];
+        let failures =
+            apply_transformations(&transformations, "X001", "X000", &mut values).unwrap();
+        let keys: Vec<&str> = failures.iter().map(|(key, _)| key.as_str()).collect();
+        assert_eq!(keys, vec!["c", "d"]);
+        assert_eq!(
+            format!("{:#}", failures[0].1),
+            "Failed to aggregate node X001 with sum: Cannot aggregate \"x\" as a number"
+        );
+        assert_eq!(
+            values,
+            HashMap::from([
+                ("a".to_string(), vec!["8".to_string()]),
+                ("b".to_string(), vec!["3".to_string()]),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_apply_root_filters() {
+        let mut rows = BTreeMap::from([
+            ("100".to_string(), ()),
+            ("101".to_string(), ()),
+            ("102".to_string(), ()),
+        ]);
+        let transformations = vec![Transformation::Filter("$X000$ >= 101".to_string())];
+        apply_root_filters(&transformations, "X000", &mut rows).unwrap();
+        assert_eq!(rows.keys().collect::<Vec<_>>(), vec!["101", "102"]);
+
+        let transformations = vec![Transformation::Filter("$X001$ = 1".to_string())];
+        assert!(apply_root_filters(&transformations, "X000", &mut rows).is_err());
+    }
+}
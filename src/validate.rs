@@ -0,0 +1,334 @@
+//! # Validate
+//!
+//! This module contains the checks of a query against the datasources and the DVM,
+//! run without reading any data.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use tracing::{debug, trace};
+
+use crate::dvmql::query::tree::TreeNode;
+use crate::load::Datasource;
+use crate::store::DvmStore;
+use crate::transform::{expression::Expression, unsupported_transformation, Transformation};
+
+/// Checks the query tree rooted at `root`, returning every problem found.
+///
+/// The checks cover the attributes of the nodes, the edges between parent and child nodes,
+/// the datasources and files these edges read, and the `$LABEL$` references of filters,
+/// maps and thetas. Edges reading one of the `invalid_datasources`, which failed to load from
+/// the datasources file, are reported as such rather than as reading an undefined datasource.
+/// Without a `store`, as when the DVM cannot be opened, the attributes and edges are not checked.
+pub async fn validate(
+    root: &TreeNode,
+    datasources: &HashMap<String, Datasource>,
+    invalid_datasources: &HashSet<String>,
+    store: Option<&dyn DvmStore>,
+) -> Result<Vec<String>> {
+    let mut problems = vec![];
+    let mut checked_datasources = HashSet::new();
+    // Walk the tree in pre-order, so that problems are reported in the order of the query
+    let mut stack: Vec<(&TreeNode, Option<&TreeNode>)> = vec![(root, None)];
+    while let Some((node, parent)) = stack.pop() {
+        trace!("Validating node {}", &node.label);
+        let has_attribute = match store {
+            Some(store) => store.has_attribute(&node.name).await?,
+            None => false,
+        };
+        if store.is_some() && !has_attribute {
+            problems.push(format!(
+                "Node {}: attribute {} does not exist in the DVM",
+                &node.label, &node.name
+            ));
+        }
+        check_transformations(node, parent, &mut problems);
+        problems.extend(unsupported_transformation(node, parent.is_none()));
+        check_theta(node, &mut problems);
+
+        for child in &node.children {
+            let Some(store) = store.filter(|_| has_attribute) else {
+                break;
+            };
+            if !store.has_attribute(&child.name).await? {
+                continue;
+            }
+            let edges = store.edges(&node.name, &child.name).await?;
+            if edges.is_empty() {
+                problems.push(format!(
+                    "No edge found between {} ({}) and {} ({})",
+                    &node.label, &node.name, &child.label, &child.name
+                ));
+            }
+            for edge in &edges {
+                let Some(datasource) = datasources.get(&edge.datasource_name) else {
+                    if invalid_datasources.contains(&edge.datasource_name) {
+                        problems.push(format!(
+                            "Edge {} -> {} reads datasource {}, which is invalid in the datasources file",
+                            &node.label, &child.label, &edge.datasource_name
+                        ));
+                        continue;
+                    }
+                    problems.push(format!(
+                        "Edge {} -> {} reads datasource {}, which is not defined in the datasources file",
+                        &node.label, &child.label, &edge.datasource_name
+                    ));
+                    continue;
+                };
+                if edge.key_pos == 0 || edge.value_pos == 0 {
+                    problems.push(format!(
+                        "Edge {} -> {} has key position {} and value position {}, but positions are 1-based",
+                        &node.label, &child.label, edge.key_pos, edge.value_pos
+                    ));
+                }
+                if let Datasource::Database(db) = datasource {
+                    if edge.query.as_deref().unwrap_or_default().is_empty() {
+                        problems.push(format!(
+                            "Edge {} -> {} reads database datasource {} without a query",
+                            &node.label, &child.label, &db.name
+                        ));
+                    }
+                }
+                if !checked_datasources.insert(edge.datasource_name.clone()) {
+                    continue;
+                }
+                if let Some(file) = datasource.file() {
+                    if !file.is_file() {
+                        problems.push(format!(
+                            "Datasource {}: file {} does not exist",
+                            &edge.datasource_name,
+                            file.display()
+                        ));
+                    }
+                }
+            }
+        }
+        stack.extend(node.children.iter().rev().map(|child| (child, Some(node))));
+    }
+    debug!("Validation found {} problems", problems.len());
+    Ok(problems)
+}
+
+/// Helper function for checking that filters and maps parse and refer to the node or its parent.
+fn check_transformations(node: &TreeNode, parent: Option<&TreeNode>, problems: &mut Vec<String>) {
+    for transformation in &node.transformations {
+        let (kind, text) = match transformation {
+            Transformation::Filter(filter) => ("filter", filter),
+            Transformation::Map(map) => ("map", map),
+            Transformation::Aggregate(_) => continue,
+        };
+        let expression = match text.parse::<Expression>() {
+            Ok(expression) => expression,
+            Err(e) => {
+                problems.push(format!(
+                    "Node {}: invalid {} \"{}\": {:#}",
+                    &node.label, kind, text, e
+                ));
+                continue;
+            }
+        };
+        for reference in expression.references() {
+            if reference != node.label && parent.map(|p| p.label.as_str()) != Some(reference) {
+                problems.push(format!(
+                    "Node {}: {} \"{}\" refers to ${}$, which is neither the node nor its parent",
+                    &node.label, kind, text, reference
+                ));
+            }
+        }
+    }
+}
+
+/// Helper function for checking that the theta parses and refers to the node or its children.
+fn check_theta(node: &TreeNode, problems: &mut Vec<String>) {
+    let Some(theta) = &node.theta else {
+        return;
+    };
+    let expression = match theta.parse::<Expression>() {
+        Ok(expression) => expression,
+        Err(e) => {
+            problems.push(format!(
+                "Node {}: invalid theta \"{}\": {:#}",
+                &node.label, theta, e
+            ));
+            return;
+        }
+    };
+    for reference in expression.references() {
+        if reference != node.label && !node.children.iter().any(|child| child.label == reference) {
+            problems.push(format!(
+                "Node {}: theta \"{}\" refers to ${}$, which is neither the node nor one of its children",
+                &node.label, theta, reference
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::load::Database;
+    use crate::store::MemoryStore;
+    use crate::test_util::{csv_datasource, dvm, node};
+
+    fn store() -> MemoryStore {
+        MemoryStore::from(dvm(&[
+            ("id", "firstname", "myCSV", "", 1, 2),
+            ("id", "lastname", "missingCSV", "", 1, 3),
+            ("id", "email", "myDb", "", 1, 4),
+            ("email", "id", "goneCSV", "", 4, 1),
+        ]))
+    }
+
+    fn datasources() -> HashMap<String, Datasource> {
+        HashMap::from([
+            (
+                String::from("myCSV"),
+                csv_datasource("myCSV", "example_csv.csv"),
+            ),
+            (
+                String::from("goneCSV"),
+                csv_datasource("goneCSV", "gone.csv"),
+            ),
+            (
+                String::from("myDb"),
+                Datasource::Database(Database {
+                    id: 3,
+                    name: String::from("myDb"),
+                    system: String::from("postgresql"),
+                    connection: String::from("localhost:5432"),
+                    username: String::from("u"),
+                    password: String::from("p"),
+                    database: String::from("d"),
+                }),
+            ),
+        ])
+    }
+
+    #[tokio::test]
+    async fn test_validate_valid_query() {
+        let mut firstname = node("firstname", "X001", vec![]);
+        firstname.transformations = vec![Transformation::Filter(String::from(
+            "$X001$ != '' and $X000$ > 0",
+        ))];
+        let mut root = node("id", "X000", vec![firstname]);
+        root.theta = Some(String::from("$X001$ != 'Lila'"));
+
+        let problems = validate(&root, &datasources(), &HashSet::new(), Some(&store()))
+            .await
+            .unwrap();
+        assert!(problems.is_empty(), "{:?}", problems);
+    }
+
+    #[tokio::test]
+    async fn test_validate_reports_every_problem() {
+        let mut firstname = node("firstname", "X001", vec![]);
+        firstname.transformations = vec![
+            Transformation::Filter(String::from("$X009$ > 1")),
+            Transformation::Map(String::from("upper(")),
+        ];
+        let email = node("email", "X003", vec![node("id", "X004", vec![])]);
+        let mut root = node(
+            "id",
+            "X000",
+            vec![
+                firstname,
+                node("lastname", "X002", vec![]),
+                email,
+                node("phone", "X005", vec![]),
+            ],
+        );
+        root.theta = Some(String::from("$X001$ = $X008$"));
+
+        let problems = validate(&root, &datasources(), &HashSet::new(), Some(&store()))
+            .await
+            .unwrap();
+        let expected = [
+            "Node X000: theta \"$X001$ = $X008$\" refers to $X008$",
+            "Edge X000 -> X002 reads datasource missingCSV, which is not defined",
+            "Edge X000 -> X003 reads database datasource myDb without a query",
+            "Node X001: filter \"$X009$ > 1\" refers to $X009$",
+            "Node X001: invalid map \"upper(\"",
+            "Datasource goneCSV: file",
+            "Node X005: attribute phone does not exist in the DVM",
+        ];
+        assert_eq!(problems.len(), expected.len(), "{:?}", problems);
+        for (problem, expected) in problems.iter().zip(expected) {
+            assert!(problem.starts_with(expected), "{}", problem);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_validate_reports_edges_of_invalid_datasources() {
+        let root = node(
+            "id",
+            "X000",
+            vec![
+                node("firstname", "X001", vec![]),
+                node("lastname", "X002", vec![]),
+            ],
+        );
+        let mut datasources = datasources();
+        datasources.remove("myCSV");
+        let invalid_datasources = HashSet::from([String::from("myCSV")]);
+
+        let problems = validate(&root, &datasources, &invalid_datasources, Some(&store()))
+            .await
+            .unwrap();
+        assert_eq!(
+            problems,
+            vec![
+                "Edge X000 -> X001 reads datasource myCSV, which is invalid in the datasources file",
+                "Edge X000 -> X002 reads datasource missingCSV, which is not defined in the datasources file",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_validate_rejects_map_on_node_with_children() {
+        let mut email = node("email", "X001", vec![node("id", "X002", vec![])]);
+        email.transformations = vec![Transformation::Map(String::from("upper($X001$)"))];
+        let root = node("id", "X000", vec![email]);
+
+        let problems = validate(&root, &datasources(), &HashSet::new(), Some(&store()))
+            .await
+            .unwrap();
+        let expected = "Node X001: map \"upper($X001$)\" is not supported on a node with children, whose rows are keyed by its original values";
+        assert!(
+            problems.iter().any(|problem| problem == expected),
+            "{:?}",
+            problems
+        );
+    }
+
+    #[tokio::test]
+    async fn test_validate_rejects_theta_on_grandchild() {
+        let email = node("email", "X001", vec![node("id", "X002", vec![])]);
+        let mut root = node("id", "X000", vec![email]);
+        root.theta = Some(String::from("$X002$ > 1"));
+
+        let problems = validate(&root, &datasources(), &HashSet::new(), Some(&store()))
+            .await
+            .unwrap();
+        let expected = "Node X000: theta \"$X002$ > 1\" refers to $X002$, which is neither the node nor one of its children";
+        assert!(
+            problems.iter().any(|problem| problem == expected),
+            "{:?}",
+            problems
+        );
+    }
+
+    #[tokio::test]
+    async fn test_validate_without_store_checks_expressions() {
+        let mut firstname = node("firstname", "X001", vec![]);
+        firstname.transformations = vec![Transformation::Filter(String::from("$X009$ > 1"))];
+        let root = node("unknown", "X000", vec![firstname]);
+
+        let problems = validate(&root, &datasources(), &HashSet::new(), None)
+            .await
+            .unwrap();
+        assert_eq!(
+            problems,
+            vec!["Node X001: filter \"$X009$ > 1\" refers to $X009$, which is neither the node nor its parent"]
+        );
+    }
+}
@@ -0,0 +1,51 @@
+use assert_cmd::Command;
+
+use data_mingler_rust::dvmql::dvm::{load_dvm_xml, DvmEdge};
+use testcontainers_modules::{neo4j::Neo4j, testcontainers::runners::AsyncRunner};
+
+fn sorted(mut edges: Vec<DvmEdge>) -> Vec<DvmEdge> {
+    edges.sort_by(|a, b| {
+        (&a.head.name, &a.tail.name, &a.datasource).cmp(&(
+            &b.head.name,
+            &b.tail.name,
+            &b.datasource,
+        ))
+    });
+    edges
+}
+
+#[ignore]
+#[tokio::test]
+async fn it_exports_the_dvm() -> Result<(), Box<dyn std::error::Error + 'static>> {
+    let container = Neo4j::new()
+        .with_version("latest")
+        .with_user("neo4j")
+        .with_password("12345678")
+        .start()
+        .await?;
+    let bolt_uri = format!(
+        "bolt://{}:{}",
+        container.get_host().await?,
+        container.image().bolt_port_ipv4()?
+    );
+
+    Command::cargo_bin("dvm-to-neo4j")
+        .unwrap()
+        .args(["--bolt-uri", bolt_uri.as_str(), "test_data/example_dvm.xml"])
+        .assert()
+        .success();
+
+    let dir = assert_fs::TempDir::new()?;
+    let exported = dir.path().join("dvm.xml");
+    Command::cargo_bin("neo4j-to-dvm")
+        .unwrap()
+        .args(["--bolt-uri", bolt_uri.as_str(), exported.to_str().unwrap()])
+        .assert()
+        .success();
+
+    let expected = load_dvm_xml("test_data/example_dvm.xml")?;
+    let actual = load_dvm_xml(exported.to_str().unwrap())?;
+    assert_eq!(sorted(actual.edges), sorted(expected.edges));
+
+    Ok(())
+}
@@ -17,7 +17,7 @@ async fn it_loads_the_dvm() -> Result<(), Box<dyn std::error::Error + 'static>>
 
     let mut cmd = Command::cargo_bin("dvm-to-neo4j").unwrap();
     let assert = cmd
-        .args(&[
+        .args([
             "-ddd",
             "--bolt-uri",
             format!(
@@ -67,3 +67,104 @@ async fn it_loads_the_dvm() -> Result<(), Box<dyn std::error::Error + 'static>>
 
     Ok(())
 }
+
+#[ignore]
+#[tokio::test]
+async fn it_loads_the_dvm_idempotently() -> Result<(), Box<dyn std::error::Error + 'static>> {
+    let container = Neo4j::new()
+        .with_version("latest")
+        .with_user("neo4j")
+        .with_password("12345678")
+        .start()
+        .await?;
+    let bolt_uri = format!(
+        "bolt://{}:{}",
+        container.get_host().await?,
+        container.image().bolt_port_ipv4()?
+    );
+
+    for _ in 0..2 {
+        Command::cargo_bin("dvm-to-neo4j")
+            .unwrap()
+            .args([
+                "--bolt-uri",
+                bolt_uri.as_str(),
+                "--use-existing-graph",
+                "test_data/example_dvm.xml",
+            ])
+            .assert()
+            .success();
+    }
+
+    let config = neo4rs::ConfigBuilder::new()
+        .uri(bolt_uri)
+        .user(container.image().user().expect("user is set"))
+        .password(container.image().password().expect("password is set"))
+        .build()?;
+    let graph = neo4rs::Graph::connect(config).await?;
+
+    let mut rows = graph
+        .execute(neo4rs::query(
+            "MATCH (n:attribute) WITH count(n) AS nodes \
+            MATCH ()-[r:has]->() RETURN nodes, count(r) AS edges",
+        ))
+        .await?;
+    let row = rows.next().await?.expect("counts are returned");
+    assert_eq!(row.get::<i64>("nodes").unwrap(), 3);
+    assert_eq!(row.get::<i64>("edges").unwrap(), 4);
+
+    Ok(())
+}
+
+#[ignore]
+#[tokio::test]
+async fn it_recomputes_primary_nodes() -> Result<(), Box<dyn std::error::Error + 'static>> {
+    let container = Neo4j::new()
+        .with_version("latest")
+        .with_user("neo4j")
+        .with_password("12345678")
+        .start()
+        .await?;
+    let bolt_uri = format!(
+        "bolt://{}:{}",
+        container.get_host().await?,
+        container.image().bolt_port_ipv4()?
+    );
+
+    Command::cargo_bin("dvm-to-neo4j")
+        .unwrap()
+        .args(["--bolt-uri", bolt_uri.as_str(), "test_data/example_dvm.xml"])
+        .assert()
+        .success();
+
+    let config = neo4rs::ConfigBuilder::new()
+        .uri(bolt_uri.as_str())
+        .user(container.image().user().expect("user is set"))
+        .password(container.image().password().expect("password is set"))
+        .build()?;
+    let graph = neo4rs::Graph::connect(config).await?;
+
+    // Edit the graph by hand, leaving every node with at most one outgoing edge
+    graph
+        .run(neo4rs::query(
+            "MATCH (n:primary)-[r:has]->() WITH n, collect(r) AS rs \
+            UNWIND rs[1..] AS r DELETE r",
+        ))
+        .await?;
+
+    Command::cargo_bin("dvm-to-neo4j")
+        .unwrap()
+        .args(["recompute-primary", "--bolt-uri", bolt_uri.as_str()])
+        .assert()
+        .success();
+
+    let mut rows = graph
+        .execute(neo4rs::query(
+            "MATCH (n:primary) RETURN count(n) AS primary",
+        ))
+        .await?;
+    let row = rows.next().await?.expect("count is returned");
+    assert_eq!(row.get::<i64>("primary").unwrap(), 0);
+
+    Ok(())
+}
@@ -0,0 +1,63 @@
+use data_mingler_rust::load::{edges::Edge, ConnectionPools, Database, Datasource};
+use deadpool_postgres::tokio_postgres::{self, NoTls};
+use testcontainers_modules::{postgres::Postgres, testcontainers::runners::AsyncRunner};
+use tokio_stream::StreamExt;
+
+#[ignore]
+#[tokio::test]
+async fn it_reads_edges_from_postgres() -> Result<(), Box<dyn std::error::Error + 'static>> {
+    let container = Postgres::default().start().await?;
+    let host = container.get_host().await?;
+    let port = container.get_host_port_ipv4(5432).await?;
+
+    let (client, connection) = tokio_postgres::connect(
+        &format!(
+            "host={} port={} user=postgres password=postgres",
+            host, port
+        ),
+        NoTls,
+    )
+    .await?;
+    tokio::spawn(connection);
+    client
+        .batch_execute(
+            "CREATE TABLE trip_time (int_id INTEGER, pickup_datetime TIMESTAMP); \
+            INSERT INTO trip_time VALUES (1, '2024-01-01 10:00:00'), (2, '2024-01-02 11:30:00');",
+        )
+        .await?;
+
+    let datasource = Datasource::Database(Database {
+        id: 1,
+        name: "mypostgresql".to_string(),
+        system: "postgresql".to_string(),
+        connection: format!("{}:{}", host, port),
+        username: "postgres".to_string(),
+        password: "postgres".to_string(),
+        database: "postgres".to_string(),
+    });
+    let edge = Edge {
+        datasource_name: "mypostgresql".to_string(),
+        key_pos: 1,
+        value_pos: 2,
+        query: Some("SELECT int_id, pickup_datetime FROM trip_time ORDER BY int_id".to_string()),
+    };
+
+    let pools = ConnectionPools::default();
+    for _ in 0..2 {
+        let records: Vec<(String, String)> = datasource
+            .read_async(&edge, &pools)
+            .await?
+            .map(|record| record.unwrap())
+            .collect()
+            .await;
+        assert_eq!(
+            records,
+            vec![
+                ("1".to_string(), "2024-01-01 10:00:00".to_string()),
+                ("2".to_string(), "2024-01-02 11:30:00".to_string()),
+            ]
+        );
+    }
+
+    Ok(())
+}